@@ -10,11 +10,12 @@
 
 use crate::api::v2;
 use crate::data::Database;
+use crate::logging::LogLevels;
 use crate::shutdown::Controller;
 use crate::types::IdentityConfig;
 use crate::{
 	api::v1,
-	network::rpc::{self},
+	network::{p2p, rpc},
 	types::{RuntimeConfig, State},
 };
 use color_eyre::eyre::WrapErr;
@@ -35,7 +36,11 @@ pub struct Server<T: Database> {
 	pub version: String,
 	pub network_version: String,
 	pub node_client: rpc::Client,
+	pub p2p_client: p2p::Client,
+	pub peer_id: String,
 	pub ws_clients: v2::types::WsClients,
+	pub log_levels: Option<LogLevels>,
+	pub head_pin: Option<rpc::HeadPin>,
 	pub shutdown: Controller<String>,
 }
 
@@ -64,8 +69,12 @@ impl<T: Database + Clone + Send + Sync + 'static> Server<T> {
 			self.cfg,
 			self.identity_cfg,
 			self.node_client.clone(),
+			self.p2p_client.clone(),
+			self.peer_id.clone(),
 			self.ws_clients.clone(),
 			self.db.clone(),
+			self.log_levels,
+			self.head_pin,
 		);
 
 		let cors = warp::cors()
@@ -1,27 +1,30 @@
 use super::{
-	transactions,
+	peers, transactions,
 	types::{
-		block_status, filter_fields, Block, BlockStatus, DataQuery, DataResponse, DataTransaction,
-		Error, FieldsQueryParameter, Header, Status, SubmitResponse, Subscription, SubscriptionId,
-		Transaction, Version, WsClients,
+		block_status, filter_fields, Block, BlockRange, BlockStatus, DataQuery, DataResponse,
+		DataTransaction, Diagnostics, Error, FieldsQueryParameter, Header, LogLevelRequest,
+		PinHeadRequest, Status, SubmitResponse, Subscription, SubscriptionId, Transaction, Version,
+		WsClients,
 	},
 	ws,
 };
 use crate::{
 	api::v2::types::{ErrorCode, InternalServerError},
-	data::Database,
-	data::Key,
+	data::{self, Database, Key},
+	logging::LogLevels,
+	network::rpc::{HeadPin, HeadPinClient},
 	types::{RuntimeConfig, State},
 	utils::calculate_confidence,
 };
-use avail_subxt::primitives;
+use avail_subxt::{primitives, utils::H256};
 use color_eyre::{eyre::eyre, Result};
 use hyper::StatusCode;
 use std::{
+	collections::HashMap,
 	convert::Infallible,
 	sync::{Arc, Mutex},
 };
-use tracing::error;
+use tracing::{error, level_filters::LevelFilter};
 use uuid::Uuid;
 use warp::{ws::Ws, Rejection, Reply};
 
@@ -71,11 +74,109 @@ pub async fn ws(
 	}))
 }
 
+pub fn log_level(log_levels: LogLevels, request: LogLevelRequest) -> Result<impl Reply, Error> {
+	let level = request
+		.level
+		.parse::<LevelFilter>()
+		.map_err(|_| Error::bad_request_unknown("Invalid log level"))?;
+	log_levels
+		.set_task_log_level(request.task, level)
+		.map_err(Error::internal_server_error)?;
+	Ok(warp::reply())
+}
+
+pub async fn pin_head(
+	head_pin: HeadPin,
+	rpc_client: impl HeadPinClient + Send + Sync,
+	state: Arc<Mutex<State>>,
+	request: PinHeadRequest,
+) -> Result<impl Reply, Error> {
+	let header = rpc_client
+		.get_header_by_hash(request.hash)
+		.await
+		.map_err(Error::internal_server_error)?;
+
+	let finalized = {
+		let state = state.lock().expect("Lock should be acquired");
+		state.finalized_header.as_ref().map(|header| header.number)
+	};
+	if finalized.is_some_and(|finalized| header.number < finalized) {
+		return Err(Error::bad_request_unknown(
+			"Block is below the finalized block",
+		));
+	}
+
+	// collect ancestors which are not finalized yet, so the head follows the pinned fork
+	let mut ancestors = HashMap::new();
+	let (mut number, mut parent_hash) = (header.number, header.parent_hash);
+	while finalized.is_some_and(|finalized| number > finalized + 1) {
+		number -= 1;
+		ancestors.insert(number, parent_hash);
+		parent_hash = rpc_client
+			.get_header_by_hash(parent_hash)
+			.await
+			.map_err(Error::internal_server_error)?
+			.parent_hash;
+	}
+
+	head_pin.pin(request.hash, header.number, ancestors);
+	Ok(warp::reply())
+}
+
+pub fn unpin_head(head_pin: HeadPin) -> impl Reply {
+	head_pin.unpin();
+	warp::reply()
+}
+
 pub fn status(config: RuntimeConfig, state: Arc<Mutex<State>>) -> impl Reply {
 	let state = state.lock().expect("Lock should be acquired");
 	Status::new(&config, &state)
 }
 
+pub async fn diagnostics(
+	version: Version,
+	config: RuntimeConfig,
+	state: Arc<Mutex<State>>,
+	p2p_client: impl peers::Peers + Send + Sync,
+	peer_id: String,
+	db: impl Database + Send,
+) -> Result<impl Reply, Error> {
+	let connected_peers = p2p_client
+		.connected_peers()
+		.await
+		.map_err(Error::internal_server_error)?;
+	let kademlia_modes = p2p_client
+		.kademlia_modes()
+		.await
+		.map_err(Error::internal_server_error)?;
+	let protocol_mismatches = p2p_client
+		.protocol_mismatches()
+		.await
+		.map_err(Error::internal_server_error)?;
+	let stored_blocks = db
+		.stored_block_range()
+		.map_err(Error::internal_server_error)?
+		.map(|(first, last)| BlockRange { first, last });
+
+	let state = state.lock().expect("Lock should be acquired");
+	Ok(Diagnostics {
+		version,
+		status: Status::new(&config, &state),
+		sync_state: state.sync_state(),
+		stored_blocks,
+		// Secret key is not included in support reports
+		config: RuntimeConfig {
+			secret_key: None,
+			..config
+		},
+		peer_id,
+		rpc_node: state.connected_node.host.clone(),
+		connected_peers,
+		kademlia_modes,
+		protocol_mismatches,
+	})
+}
+
 pub fn log_internal_server_error(result: Result<impl Reply, Error>) -> Result<impl Reply, Error> {
 	if let Err(Error {
 		error_code: ErrorCode::InternalServerError,
@@ -134,6 +235,14 @@ pub async fn block_header(
 		.map_err(Error::internal_server_error)
 }
 
+pub async fn block_header_by_hash(block_hash: H256, db: impl Database) -> Result<Header, Error> {
+	data::get_block_header_by_hash(&db, block_hash)
+		.map_err(Error::internal_server_error)?
+		.ok_or_else(Error::not_found)?
+		.try_into()
+		.map_err(Error::internal_server_error)
+}
+
 pub async fn block_data(
 	block_number: u32,
 	query: DataQuery,
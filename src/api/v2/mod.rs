@@ -1,4 +1,4 @@
-use avail_subxt::AvailConfig;
+use avail_subxt::{utils::H256, AvailConfig};
 use sp_core::sr25519::Pair;
 use std::{
 	convert::Infallible,
@@ -18,11 +18,16 @@ use self::{
 use crate::{
 	api::v2::types::Topic,
 	data::Database,
-	network::rpc::Client,
+	logging::LogLevels,
+	network::{
+		p2p,
+		rpc::{Client, HeadPin, HeadPinClient},
+	},
 	types::{IdentityConfig, RuntimeConfig, State},
 };
 
 mod handlers;
+mod peers;
 mod transactions;
 pub mod types;
 mod ws;
@@ -65,6 +70,26 @@ fn status_route(
 		.map(handlers::status)
 }
 
+fn diagnostics_route(
+	version: Version,
+	config: RuntimeConfig,
+	state: Arc<Mutex<State>>,
+	p2p_client: impl peers::Peers + Clone + Send + Sync + 'static,
+	peer_id: String,
+	db: impl Database + Clone + Send,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path!("v2" / "diagnostics")
+		.and(warp::get())
+		.and(warp::any().map(move || version.clone()))
+		.and(warp::any().map(move || config.clone()))
+		.and(warp::any().map(move || state.clone()))
+		.and(warp::any().map(move || p2p_client.clone()))
+		.and(warp::any().map(move || peer_id.clone()))
+		.and(with_db(db))
+		.then(handlers::diagnostics)
+		.map(log_internal_server_error)
+}
+
 fn block_route(
 	config: RuntimeConfig,
 	state: Arc<Mutex<State>>,
@@ -93,6 +118,16 @@ fn block_header_route(
 		.map(log_internal_server_error)
 }
 
+fn block_header_by_hash_route(
+	db: impl Database + Clone + Send,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path!("v2" / "headers" / H256)
+		.and(warp::get())
+		.and(with_db(db))
+		.then(handlers::block_header_by_hash)
+		.map(log_internal_server_error)
+}
+
 fn block_data_route(
 	config: RuntimeConfig,
 	state: Arc<Mutex<State>>,
@@ -119,6 +154,41 @@ fn submit_route(
 		.map(log_internal_server_error)
 }
 
+fn log_level_route(
+	log_levels: Option<LogLevels>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path!("v2" / "log-levels")
+		.and(warp::post())
+		.and_then(move || optionally(log_levels.clone()))
+		.and(warp::body::json())
+		.map(handlers::log_level)
+		.map(log_internal_server_error)
+}
+
+fn pin_head_route(
+	head_pin: Option<HeadPin>,
+	rpc_client: impl HeadPinClient + Clone + Send + Sync + 'static,
+	state: Arc<Mutex<State>>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path!("v2" / "head-pin")
+		.and(warp::post())
+		.and_then(move || optionally(head_pin.clone()))
+		.and(warp::any().map(move || rpc_client.clone()))
+		.and(warp::any().map(move || state.clone()))
+		.and(warp::body::json())
+		.then(handlers::pin_head)
+		.map(log_internal_server_error)
+}
+
+fn unpin_head_route(
+	head_pin: Option<HeadPin>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path!("v2" / "head-pin")
+		.and(warp::delete())
+		.and_then(move || optionally(head_pin.clone()))
+		.map(handlers::unpin_head)
+}
+
 fn subscriptions_route(
 	clients: WsClients,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
@@ -146,12 +216,12 @@ fn ws_route(
 		.and_then(handlers::ws)
 }
 
-pub async fn publish<T: Clone + TryInto<PublishMessage>>(
+pub async fn publish<T: Clone + TryInto<Option<PublishMessage>>>(
 	topic: Topic,
 	mut receiver: broadcast::Receiver<T>,
 	clients: WsClients,
 ) where
-	<T as TryInto<PublishMessage>>::Error: Display,
+	<T as TryInto<Option<PublishMessage>>>::Error: Display,
 {
 	loop {
 		let message = match receiver.recv().await {
@@ -163,7 +233,8 @@ pub async fn publish<T: Clone + TryInto<PublishMessage>>(
 		};
 
 		let message: PublishMessage = match message.try_into() {
-			Ok(message) => message,
+			Ok(Some(message)) => message,
+			Ok(None) => continue,
 			Err(error) => {
 				error!(?topic, "Cannot create message: {error}");
 				continue;
@@ -192,8 +263,12 @@ pub fn routes(
 	config: RuntimeConfig,
 	identity_config: IdentityConfig,
 	rpc_client: Client,
+	p2p_client: p2p::Client,
+	peer_id: String,
 	ws_clients: WsClients,
 	db: impl Database + Clone + Send,
+	log_levels: Option<LogLevels>,
+	head_pin: Option<HeadPin>,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
 	let version = Version {
 		version,
@@ -205,7 +280,7 @@ pub fn routes(
 
 	let submitter = app_id.map(|&app_id| {
 		Arc::new(transactions::Submitter {
-			rpc_client,
+			rpc_client: rpc_client.clone(),
 			app_id,
 			pair_signer,
 		})
@@ -213,30 +288,44 @@ pub fn routes(
 
 	version_route(version.clone())
 		.or(status_route(config.clone(), state.clone()))
+		.or(diagnostics_route(
+			version.clone(),
+			config.clone(),
+			state.clone(),
+			p2p_client,
+			peer_id,
+			db.clone(),
+		))
 		.or(block_route(config.clone(), state.clone(), db.clone()))
 		.or(block_header_route(
 			config.clone(),
 			state.clone(),
 			db.clone(),
 		))
+		.or(block_header_by_hash_route(db.clone()))
 		.or(block_data_route(config.clone(), state.clone(), db.clone()))
 		.or(subscriptions_route(ws_clients.clone()))
 		.or(submit_route(submitter.clone()))
+		.or(log_level_route(log_levels))
+		.or(pin_head_route(head_pin.clone(), rpc_client, state.clone()))
+		.or(unpin_head_route(head_pin))
 		.or(ws_route(ws_clients, version, config, submitter, state))
 		.recover(handle_rejection)
 }
 
 #[cfg(test)]
 mod tests {
-	use super::{transactions, types::Transaction};
+	use super::{peers, transactions, types::Transaction};
 	use crate::{
 		api::v2::types::{
 			DataField, ErrorCode, SubmitResponse, Subscription, SubscriptionId, Topic, Version,
 			WsClients, WsError, WsResponse,
 		},
 		data::Key,
-		data::{mem_db, Database},
-		types::{BlockRange, OptionBlockRange, RuntimeConfig, State},
+		data::{self, mem_db, Database},
+		logging::LogLevels,
+		network::rpc::{HeadPin, HeadPinClient},
+		types::{BlockRange, OptionBlockRange, RuntimeConfig, SecretKey, State},
 	};
 	use async_trait::async_trait;
 	use avail_subxt::utils::H256;
@@ -251,7 +340,7 @@ mod tests {
 	use hyper::StatusCode;
 	use kate_recovery::matrix::Partition;
 	use std::{
-		collections::HashSet,
+		collections::{HashMap, HashSet},
 		str::FromStr,
 		sync::{Arc, Mutex},
 	};
@@ -295,7 +384,7 @@ mod tests {
 
 		let gen_hash = H256::default();
 		let expected = format!(
-			r#"{{"modes":["light"],"genesis_hash":"{:x?}","network":"{NETWORK}","blocks":{{"latest":0}}}}"#,
+			r#"{{"modes":["light"],"genesis_hash":"{:x?}","chain_name":"{{chain_name}}","network":"{NETWORK}","blocks":{{"latest":0}}}}"#,
 			gen_hash
 		);
 		assert_eq!(response.body(), &expected);
@@ -336,7 +425,29 @@ mod tests {
 
 		let gen_hash = H256::default();
 		let expected = format!(
-			r#"{{"modes":["light","app","partition"],"app_id":1,"genesis_hash":"{:#x}","network":"{NETWORK}","blocks":{{"latest":30,"available":{{"first":20,"last":29}},"app_data":{{"first":20,"last":29}},"historical_sync":{{"synced":false,"available":{{"first":10,"last":19}},"app_data":{{"first":10,"last":18}}}}}},"partition":"1/10"}}"#,
+			r#"{{"modes":["light","app","partition"],"app_id":1,"genesis_hash":"{:#x}","chain_name":"{{chain_name}}","network":"{NETWORK}","blocks":{{"latest":30,"available":{{"first":20,"last":29}},"app_data":{{"first":20,"last":29}},"historical_sync":{{"synced":false,"available":{{"first":10,"last":19}},"app_data":{{"first":10,"last":18}}}}}},"partition":"1/10"}}"#,
+			gen_hash
+		);
+		assert_eq!(response.body(), &expected);
+	}
+
+	#[tokio::test]
+	async fn status_route_finalized() {
+		let state = Arc::new(Mutex::new(State {
+			latest: 1,
+			finalized_header: Some(header()),
+			..Default::default()
+		}));
+		let route = super::status_route(RuntimeConfig::default(), state);
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/status")
+			.reply(&route)
+			.await;
+
+		let gen_hash = H256::default();
+		let expected = format!(
+			r#"{{"modes":["light"],"genesis_hash":"{:x?}","chain_name":"{{chain_name}}","network":"{NETWORK}","blocks":{{"latest":1,"finalized":{{"number":1,"hash":"0xb4ab92948e78b5e3115d2ce5ff2207e7d713a7fb33f4a9240e413c00954f244b"}}}}}}"#,
 			gen_hash
 		);
 		assert_eq!(response.body(), &expected);
@@ -451,6 +562,90 @@ mod tests {
 		}
 	}
 
+	#[derive(Clone)]
+	struct MockPeers {}
+
+	#[async_trait]
+	impl peers::Peers for MockPeers {
+		async fn connected_peers(&self) -> color_eyre::Result<Vec<String>> {
+			Ok(vec!["peer-1".to_string()])
+		}
+
+		async fn kademlia_modes(&self) -> color_eyre::Result<HashMap<String, usize>> {
+			Ok(HashMap::from([("server".to_string(), 1)]))
+		}
+
+		async fn protocol_mismatches(&self) -> color_eyre::Result<HashMap<String, String>> {
+			Ok(HashMap::from([(
+				"peer-2".to_string(),
+				"/other/1.0".to_string(),
+			)]))
+		}
+	}
+
+	#[tokio::test]
+	async fn diagnostics_route() {
+		let config = RuntimeConfig {
+			secret_key: Some(SecretKey::Seed {
+				seed: "secret".to_string(),
+			}),
+			..Default::default()
+		};
+		let state = Arc::new(Mutex::new(State::default()));
+		let db = mem_db::MemoryDB::default();
+		let header = header();
+		data::store_block_header(&db, header.number, &header).unwrap();
+		let route = super::diagnostics_route(
+			v1(),
+			config,
+			state,
+			MockPeers {},
+			"local-peer".to_string(),
+			db,
+		);
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/diagnostics")
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::OK);
+
+		let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+		assert_eq!(body["peer_id"], "local-peer");
+		assert_eq!(body["sync_state"], "Idle");
+		assert_eq!(
+			body["stored_blocks"],
+			serde_json::json!({"first": 1, "last": 1})
+		);
+		assert_eq!(body["connected_peers"], serde_json::json!(["peer-1"]));
+		assert_eq!(body["kademlia_modes"]["server"], 1);
+		assert_eq!(body["protocol_mismatches"]["peer-2"], "/other/1.0");
+		assert!(body["config"].is_object());
+		assert!(body["config"]["secret_key"].is_null());
+	}
+
+	#[tokio::test]
+	async fn block_header_by_hash_route() {
+		let db = mem_db::MemoryDB::default();
+		let header = header();
+		data::store_block_header(&db, header.number, &header).unwrap();
+		let route = super::block_header_by_hash_route(db);
+
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/headers/0xb4ab92948e78b5e3115d2ce5ff2207e7d713a7fb33f4a9240e413c00954f244b")
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::OK);
+
+		let response = warp::test::request()
+			.method("GET")
+			.path(&format!("/v2/headers/{:#x}", H256::repeat_byte(1)))
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+	}
+
 	#[tokio::test]
 	async fn block_header_route_ok() {
 		let config = RuntimeConfig::default();
@@ -469,7 +664,7 @@ mod tests {
 			.await;
 		assert_eq!(
 			response.body(),
-			r#"{"hash":"0xb4ab92948e78b5e3115d2ce5ff2207e7d713a7fb33f4a9240e413c00954f244b","parent_hash":"0x0000000000000000000000000000000000000000000000000000000000000000","number":1,"state_root":"0x0000000000000000000000000000000000000000000000000000000000000000","extrinsics_root":"0x0000000000000000000000000000000000000000000000000000000000000000","extension":{"rows":0,"cols":0,"data_root":"0x0000000000000000000000000000000000000000000000000000000000000000","commitments":[],"app_lookup":{"size":0,"index":[]}}}"#
+			r#"{"hash":"0xb4ab92948e78b5e3115d2ce5ff2207e7d713a7fb33f4a9240e413c00954f244b","parent_hash":"0x0000000000000000000000000000000000000000000000000000000000000000","number":1,"state_root":"0x0000000000000000000000000000000000000000000000000000000000000000","extrinsics_root":"0x0000000000000000000000000000000000000000000000000000000000000000","extension":{"rows":0,"cols":0,"data_root":"0x0000000000000000000000000000000000000000000000000000000000000000","commitments":[],"app_lookup":{"size":0,"index":[]}},"digest":{"logs":[]}}"#
 		);
 	}
 
@@ -649,6 +844,150 @@ mod tests {
 		let _ = serde_json::to_string(&response).unwrap();
 	}
 
+	#[test_case(r#"{"task":"network","level":"debug"}"#, StatusCode::OK ; "Valid log level")]
+	#[test_case(r#"{"task":"network","level":"verbose"}"#, StatusCode::BAD_REQUEST ; "Invalid log level")]
+	#[test_case(r#"{"task":"unknown","level":"debug"}"#, StatusCode::BAD_REQUEST ; "Invalid task")]
+	#[tokio::test]
+	async fn log_level_route(body: &str, status: StatusCode) {
+		let log_levels = LogLevels::new(tracing::Level::INFO, |_| Ok(()));
+		let route = super::log_level_route(Some(log_levels));
+		let response = warp::test::request()
+			.method("POST")
+			.path("/v2/log-levels")
+			.body(body)
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), status);
+	}
+
+	#[tokio::test]
+	async fn log_level_route_not_configured() {
+		let route = super::log_level_route(None);
+		let response = warp::test::request()
+			.method("POST")
+			.path("/v2/log-levels")
+			.body(r#"{"task":"network","level":"debug"}"#)
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+	}
+
+	#[derive(Clone, Default)]
+	struct MockHeadPinClient {
+		headers: HashMap<H256, DaHeader>,
+	}
+
+	impl MockHeadPinClient {
+		/// Returns headers with hashes, with the parent hashes pointing to the previous block
+		fn chain(from: u32, to: u32) -> Self {
+			let headers = (from..=to)
+				.map(|number| {
+					let header = DaHeader {
+						number,
+						parent_hash: H256::repeat_byte(number as u8 - 1),
+						..header()
+					};
+					(H256::repeat_byte(number as u8), header)
+				})
+				.collect();
+			MockHeadPinClient { headers }
+		}
+	}
+
+	#[async_trait]
+	impl HeadPinClient for MockHeadPinClient {
+		async fn get_header_by_hash(&self, block_hash: H256) -> color_eyre::Result<DaHeader> {
+			self.headers
+				.get(&block_hash)
+				.cloned()
+				.ok_or_else(|| color_eyre::eyre::eyre!("Header not found"))
+		}
+	}
+
+	fn finalized_state(number: u32) -> Arc<Mutex<State>> {
+		Arc::new(Mutex::new(State {
+			finalized_header: Some(DaHeader { number, ..header() }),
+			..Default::default()
+		}))
+	}
+
+	#[tokio::test]
+	async fn pin_head_route() {
+		let head_pin = HeadPin::default();
+		let rpc_client = MockHeadPinClient::chain(8, 10);
+		let route = super::pin_head_route(Some(head_pin.clone()), rpc_client, finalized_state(8));
+		let hash = H256::repeat_byte(10);
+		let response = warp::test::request()
+			.method("POST")
+			.path("/v2/head-pin")
+			.json(&serde_json::json!({ "hash": hash }))
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::OK);
+		assert_eq!(head_pin.pinned(), Some((hash, 10)));
+	}
+
+	#[tokio::test]
+	async fn pin_head_route_below_finalized() {
+		let head_pin = HeadPin::default();
+		let rpc_client = MockHeadPinClient::chain(8, 10);
+		let route = super::pin_head_route(Some(head_pin.clone()), rpc_client, finalized_state(9));
+		let response = warp::test::request()
+			.method("POST")
+			.path("/v2/head-pin")
+			.json(&serde_json::json!({ "hash": H256::repeat_byte(8) }))
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+		assert_eq!(head_pin.pinned(), None);
+	}
+
+	#[tokio::test]
+	async fn pin_head_route_unknown_block() {
+		let head_pin = HeadPin::default();
+		let rpc_client = MockHeadPinClient::chain(8, 10);
+		let route = super::pin_head_route(Some(head_pin.clone()), rpc_client, finalized_state(8));
+		let response = warp::test::request()
+			.method("POST")
+			.path("/v2/head-pin")
+			.json(&serde_json::json!({ "hash": H256::repeat_byte(11) }))
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+		assert_eq!(head_pin.pinned(), None);
+
+		let route = super::pin_head_route(None, MockHeadPinClient::default(), finalized_state(8));
+		let response = warp::test::request()
+			.method("POST")
+			.path("/v2/head-pin")
+			.json(&serde_json::json!({ "hash": H256::repeat_byte(10) }))
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+	}
+
+	#[tokio::test]
+	async fn unpin_head_route() {
+		let head_pin = HeadPin::default();
+		head_pin.pin(H256::repeat_byte(1), 10, HashMap::new());
+		let route = super::unpin_head_route(Some(head_pin.clone()));
+		let response = warp::test::request()
+			.method("DELETE")
+			.path("/v2/head-pin")
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::OK);
+		assert_eq!(head_pin.pinned(), None);
+
+		let route = super::unpin_head_route(None);
+		let response = warp::test::request()
+			.method("DELETE")
+			.path("/v2/head-pin")
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+	}
+
 	#[tokio::test]
 	async fn subscriptions_route() {
 		let clients = WsClients::default();
@@ -753,7 +1092,7 @@ mod tests {
 
 		let gen_hash = H256::default();
 		let expected = format!(
-			r#"{{"topic":"status","request_id":"363c71fc-90f7-4276-a5b6-bec688bf01e2","message":{{"modes":["light","app","partition"],"app_id":1,"genesis_hash":"{:x?}","network":"{NETWORK}","blocks":{{"latest":30,"available":{{"first":20,"last":29}},"app_data":{{"first":20,"last":29}},"historical_sync":{{"synced":false,"available":{{"first":10,"last":19}},"app_data":{{"first":10,"last":18}}}}}},"partition":"1/10"}}}}"#,
+			r#"{{"topic":"status","request_id":"363c71fc-90f7-4276-a5b6-bec688bf01e2","message":{{"modes":["light","app","partition"],"app_id":1,"genesis_hash":"{:x?}","chain_name":"{{chain_name}}","network":"{NETWORK}","blocks":{{"latest":30,"available":{{"first":20,"last":29}},"app_data":{{"first":20,"last":29}},"historical_sync":{{"synced":false,"available":{{"first":10,"last":19}},"app_data":{{"first":10,"last":18}}}}}},"partition":"1/10"}}}}"#,
 			gen_hash
 		);
 
@@ -0,0 +1,35 @@
+use async_trait::async_trait;
+use color_eyre::Result;
+use std::collections::HashMap;
+
+use crate::network::p2p;
+
+#[async_trait]
+pub trait Peers {
+	async fn connected_peers(&self) -> Result<Vec<String>>;
+	async fn kademlia_modes(&self) -> Result<HashMap<String, usize>>;
+	async fn protocol_mismatches(&self) -> Result<HashMap<String, String>>;
+}
+
+#[async_trait]
+impl Peers for p2p::Client {
+	async fn connected_peers(&self) -> Result<Vec<String>> {
+		self.list_connected_peers().await
+	}
+
+	async fn kademlia_modes(&self) -> Result<HashMap<String, usize>> {
+		let counts = self.count_peers_by_kademlia_mode().await?;
+		Ok(counts
+			.into_iter()
+			.map(|(mode, count)| (mode.to_string(), count))
+			.collect())
+	}
+
+	async fn protocol_mismatches(&self) -> Result<HashMap<String, String>> {
+		let mismatches = self.list_protocol_mismatches().await?;
+		Ok(mismatches
+			.into_iter()
+			.map(|(peer_id, protocol)| (peer_id.to_string(), protocol))
+			.collect())
+	}
+}
@@ -1,8 +1,9 @@
 use async_trait::async_trait;
 use avail_subxt::{api, primitives::AvailExtrinsicParams, AvailConfig};
-use color_eyre::Result;
+use color_eyre::{eyre::eyre, Result};
 use sp_core::sr25519::Pair;
 use subxt::tx::PairSigner;
+use tracing::debug;
 
 use super::types::{SubmitResponse, Transaction};
 use crate::network::rpc;
@@ -22,21 +23,30 @@ pub struct Submitter {
 #[async_trait]
 impl Submit for Submitter {
 	async fn submit(&self, transaction: Transaction) -> Result<SubmitResponse> {
-		let ex_event = match transaction {
+		let tx_bytes = match transaction {
 			Transaction::Data(data) => {
 				let extrinsic = api::tx().data_availability().submit_data(data.into());
 				let params = AvailExtrinsicParams::new_with_app_id(self.app_id.into());
 				self.rpc_client
-					.submit_signed_and_wait_for_finalized(&extrinsic, &self.pair_signer, params)
-					.await?
-			},
-			Transaction::Extrinsic(extrinsic) => {
-				self.rpc_client
-					.submit_from_bytes_and_wait_for_finalized(extrinsic.into())
+					.create_signed(&extrinsic, &self.pair_signer, params)
 					.await?
 			},
+			Transaction::Extrinsic(extrinsic) => extrinsic.into(),
 		};
 
+		// Avoid broadcasting transactions which are rejected by the runtime
+		let valid_transaction = self
+			.rpc_client
+			.validate_transaction(tx_bytes.clone())
+			.await?
+			.map_err(|error| eyre!("Transaction validation failed: {error}"))?;
+		debug!(?valid_transaction, "Transaction is valid");
+
+		let ex_event = self
+			.rpc_client
+			.submit_from_bytes_and_wait_for_finalized(tx_bytes)
+			.await?;
+
 		let block_number = self
 			.rpc_client
 			.get_header_by_hash(ex_event.block_hash())
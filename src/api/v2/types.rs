@@ -1,6 +1,9 @@
-use avail_subxt::api::runtime_types::{
-	avail_core::{data_lookup::compact::CompactDataLookup, header::extension::HeaderExtension},
-	bounded_collections::bounded_vec::BoundedVec,
+use avail_subxt::{
+	api::runtime_types::{
+		avail_core::{data_lookup::compact::CompactDataLookup, header::extension::HeaderExtension},
+		bounded_collections::bounded_vec::BoundedVec,
+	},
+	config::substrate,
 };
 use base64::{engine::general_purpose, DecodeError, Engine};
 use codec::Encode;
@@ -12,10 +15,11 @@ use derive_more::From;
 use hyper::{http, StatusCode};
 use kate_recovery::{com::AppData, commitments, config, matrix::Partition};
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
-use sp_core::{blake2_256, H256};
+use sp_core::{blake2_256, Bytes, H256};
 use std::{
 	collections::{HashMap, HashSet},
 	sync::Arc,
+	time::Duration,
 };
 use tokio::sync::{mpsc::UnboundedSender, RwLock};
 use uuid::Uuid;
@@ -25,9 +29,11 @@ use warp::{
 };
 
 use crate::{
+	logging::TaskKind,
 	network::rpc::Event as RpcEvent,
 	types::{
 		self, block_matrix_partition_format, BlockVerified, OptionBlockRange, RuntimeConfig, State,
+		SyncState,
 	},
 	utils::decode_app_data,
 };
@@ -73,15 +79,25 @@ pub struct HistoricalSync {
 	pub app_data: Option<BlockRange>,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct FinalizedBlock {
+	pub number: u32,
+	pub hash: H256,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Blocks {
 	pub latest: u32,
 	#[serde(skip_serializing_if = "Option::is_none")]
+	pub finalized: Option<FinalizedBlock>,
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub available: Option<BlockRange>,
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub app_data: Option<BlockRange>,
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub historical_sync: Option<HistoricalSync>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub average_block_time: Option<f64>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -90,6 +106,7 @@ pub struct Status {
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub app_id: Option<u32>,
 	pub genesis_hash: String,
+	pub chain_name: String,
 	pub network: String,
 	pub blocks: Blocks,
 	#[serde(
@@ -167,11 +184,21 @@ impl Status {
 			app_data: state.sync_data_verified.as_ref().map(From::from),
 		});
 
+		let finalized = state
+			.finalized_header
+			.as_ref()
+			.map(|header| FinalizedBlock {
+				number: header.number,
+				hash: Encode::using_encoded(header, blake2_256).into(),
+			});
+
 		let blocks = Blocks {
 			latest: state.latest,
+			finalized,
 			available: state.confidence_achieved.as_ref().map(From::from),
 			app_data: state.data_verified.as_ref().map(From::from),
 			historical_sync,
+			average_block_time: state.average_block_time.as_ref().map(Duration::as_secs_f64),
 		};
 
 		let node = state.connected_node.clone();
@@ -180,6 +207,7 @@ impl Status {
 			modes: config.into(),
 			app_id: config.app_id,
 			genesis_hash: format!("{:?}", node.genesis_hash),
+			chain_name: node.chain_name.clone(),
 			network: node.network(),
 			blocks,
 			partition: config.block_matrix_partition,
@@ -215,6 +243,26 @@ impl Reply for Status {
 	}
 }
 
+#[derive(Serialize)]
+pub struct Diagnostics {
+	pub version: Version,
+	pub status: Status,
+	pub sync_state: SyncState,
+	pub stored_blocks: Option<BlockRange>,
+	pub config: RuntimeConfig,
+	pub peer_id: String,
+	pub rpc_node: String,
+	pub connected_peers: Vec<String>,
+	pub kademlia_modes: HashMap<String, usize>,
+	pub protocol_mismatches: HashMap<String, String>,
+}
+
+impl Reply for Diagnostics {
+	fn into_response(self) -> warp::reply::Response {
+		warp::reply::json(&self).into_response()
+	}
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
 #[serde(rename_all = "kebab-case")]
 pub enum Topic {
@@ -339,6 +387,7 @@ pub struct Header {
 	state_root: H256,
 	extrinsics_root: H256,
 	extension: Extension,
+	digest: Digest,
 }
 
 impl Reply for Header {
@@ -395,6 +444,54 @@ struct Extension {
 	app_lookup: CompactDataLookup,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Digest {
+	logs: Vec<DigestItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum DigestItem {
+	PreRuntime { engine_id: String, data: Bytes },
+	Consensus { engine_id: String, data: Bytes },
+	Seal { engine_id: String, data: Bytes },
+	Other { data: Bytes },
+	RuntimeEnvironmentUpdated,
+}
+
+impl From<substrate::Digest> for Digest {
+	fn from(digest: substrate::Digest) -> Self {
+		Digest {
+			logs: digest.logs.into_iter().map(From::from).collect(),
+		}
+	}
+}
+
+impl From<substrate::DigestItem> for DigestItem {
+	fn from(item: substrate::DigestItem) -> Self {
+		// Consensus engine IDs are four ASCII characters (e.g. BABE, FRNK)
+		let engine_id = |id: [u8; 4]| String::from_utf8_lossy(&id).into_owned();
+		match item {
+			substrate::DigestItem::PreRuntime(id, data) => DigestItem::PreRuntime {
+				engine_id: engine_id(id),
+				data: data.into(),
+			},
+			substrate::DigestItem::Consensus(id, data) => DigestItem::Consensus {
+				engine_id: engine_id(id),
+				data: data.into(),
+			},
+			substrate::DigestItem::Seal(id, data) => DigestItem::Seal {
+				engine_id: engine_id(id),
+				data: data.into(),
+			},
+			substrate::DigestItem::Other(data) => DigestItem::Other { data: data.into() },
+			substrate::DigestItem::RuntimeEnvironmentUpdated => {
+				DigestItem::RuntimeEnvironmentUpdated
+			},
+		}
+	}
+}
+
 impl TryFrom<avail_subxt::primitives::Header> for Header {
 	type Error = Report;
 
@@ -406,6 +503,7 @@ impl TryFrom<avail_subxt::primitives::Header> for Header {
 			state_root: header.state_root,
 			extrinsics_root: header.extrinsics_root,
 			extension: header.extension.try_into()?,
+			digest: header.digest.into(),
 		})
 	}
 }
@@ -433,7 +531,7 @@ impl TryFrom<HeaderExtension> for Extension {
 	}
 }
 
-impl TryFrom<RpcEvent> for PublishMessage {
+impl TryFrom<RpcEvent> for Option<PublishMessage> {
 	type Error = Report;
 
 	fn try_from(value: RpcEvent) -> Result<Self, Self::Error> {
@@ -441,7 +539,9 @@ impl TryFrom<RpcEvent> for PublishMessage {
 			RpcEvent::HeaderUpdate { header, .. } => header
 				.try_into()
 				.map(Box::new)
-				.map(PublishMessage::HeaderVerified),
+				.map(PublishMessage::HeaderVerified)
+				.map(Some),
+			_ => Ok(None),
 		}
 	}
 }
@@ -453,14 +553,16 @@ pub struct ConfidenceMessage {
 	confidence: Option<f64>,
 }
 
-impl TryFrom<BlockVerified> for PublishMessage {
+impl TryFrom<BlockVerified> for Option<PublishMessage> {
 	type Error = Report;
 
 	fn try_from(value: BlockVerified) -> Result<Self, Self::Error> {
-		Ok(PublishMessage::ConfidenceAchieved(ConfidenceMessage {
-			block_number: value.block_num,
-			confidence: value.confidence,
-		}))
+		Ok(Some(PublishMessage::ConfidenceAchieved(
+			ConfidenceMessage {
+				block_number: value.block_num,
+				confidence: value.confidence,
+			},
+		)))
 	}
 }
 
@@ -481,6 +583,17 @@ impl TryFrom<String> for FieldsQueryParameter {
 	}
 }
 
+#[derive(Deserialize)]
+pub struct LogLevelRequest {
+	pub task: TaskKind,
+	pub level: String,
+}
+
+#[derive(Deserialize)]
+pub struct PinHeadRequest {
+	pub hash: H256,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct DataQuery {
 	pub fields: Option<FieldsQueryParameter>,
@@ -536,7 +649,7 @@ pub fn filter_fields(data_transactions: &mut [DataTransaction], fields: &HashSet
 	}
 }
 
-impl TryFrom<(u32, AppData)> for PublishMessage {
+impl TryFrom<(u32, AppData)> for Option<PublishMessage> {
 	type Error = Report;
 
 	fn try_from((block_number, app_data): (u32, AppData)) -> Result<Self, Self::Error> {
@@ -544,10 +657,10 @@ impl TryFrom<(u32, AppData)> for PublishMessage {
 			.into_iter()
 			.map(TryFrom::try_from)
 			.collect::<Result<Vec<_>>>()?;
-		Ok(PublishMessage::DataVerified(DataMessage {
+		Ok(Some(PublishMessage::DataVerified(DataMessage {
 			block_number,
 			data_transactions,
-		}))
+		})))
 	}
 }
 
@@ -840,6 +953,7 @@ mod tests {
 						index: vec![],
 					},
 				},
+				digest: super::Digest { logs: vec![] },
 			},
 		}))
 	}
@@ -1076,4 +1190,21 @@ mod tests {
 		assert_eq!(block_status(&Some(1), &state, 5), finished);
 		assert_ne!(block_status(&Some(1), &state, 6), finished);
 	}
+
+	#[test]
+	fn digest_item_serialization() {
+		let item: super::DigestItem =
+			avail_subxt::config::substrate::DigestItem::PreRuntime(*b"BABE", vec![1, 2]).into();
+		assert_eq!(
+			serde_json::to_string(&item).unwrap(),
+			r#"{"type":"pre-runtime","engine_id":"BABE","data":"0x0102"}"#
+		);
+
+		let item: super::DigestItem =
+			avail_subxt::config::substrate::DigestItem::RuntimeEnvironmentUpdated.into();
+		assert_eq!(
+			serde_json::to_string(&item).unwrap(),
+			r#"{"type":"runtime-environment-updated"}"#
+		);
+	}
 }
@@ -4,14 +4,16 @@ use avail_core::AppId;
 use avail_light::{
 	api,
 	consts::EXPECTED_SYSTEM_VERSION,
-	data::rocks_db::RocksDB,
+	data::{self, rocks_db::RocksDB, Database, Key, KnownPeer},
+	logging::{LogLevels, TaskKind},
 	maintenance::StaticConfigParams,
 	network::{self, p2p, rpc},
 	shutdown::Controller,
+	supervisor::{self, Supervisor},
 	sync_client::SyncClient,
 	sync_finality::SyncFinality,
 	telemetry::{self, otlp::MetricAttributes},
-	types::{CliOpts, IdentityConfig, LibP2PConfig, RuntimeConfig, State},
+	types::{CliOpts, IdentityConfig, LibP2PConfig, RuntimeConfig, State, SyncClientConfig},
 };
 use clap::Parser;
 use color_eyre::{
@@ -19,15 +21,19 @@ use color_eyre::{
 	Result,
 };
 use kate_recovery::com::AppData;
-use libp2p::{multiaddr::Protocol, Multiaddr};
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
 use std::{
 	fs,
 	net::Ipv4Addr,
 	path::Path,
 	sync::{Arc, Mutex},
+	time::Duration,
 };
-use tokio::sync::{broadcast, mpsc, RwLock};
-use tracing::{error, info, metadata::ParseLevelError, trace, warn, Level, Subscriber};
+use tokio::{
+	sync::{broadcast, RwLock},
+	time::{interval_at, Instant},
+};
+use tracing::{debug, error, info, metadata::ParseLevelError, trace, warn, Level, Subscriber};
 use tracing_subscriber::{fmt::format, EnvFilter, FmtSubscriber};
 
 #[cfg(feature = "network-analysis")]
@@ -46,20 +52,36 @@ const CLIENT_ROLE: &str = if cfg!(feature = "crawl") {
 	"lightnode"
 };
 
+/// Maximum number of known peers persisted to the database
+const MAX_PERSISTED_PEERS: usize = 50;
+
+/// Interval in which known and denied peers are persisted to the database
+const PERSIST_PEERS_INTERVAL: Duration = Duration::from_secs(60);
+
 /// Light Client for Avail Blockchain
 
-fn json_subscriber(log_level: Level) -> impl Subscriber + Send + Sync {
-	FmtSubscriber::builder()
+fn json_subscriber(log_level: Level) -> (impl Subscriber + Send + Sync, LogLevels) {
+	let builder = FmtSubscriber::builder()
 		.with_env_filter(EnvFilter::new(format!("avail_light={log_level}")))
 		.event_format(format::json())
-		.finish()
+		.with_filter_reloading();
+	let handle = builder.reload_handle();
+	let log_levels = LogLevels::new(log_level, move |filter| {
+		handle.reload(filter).map_err(|error| eyre!(error))
+	});
+	(builder.finish(), log_levels)
 }
 
-fn default_subscriber(log_level: Level) -> impl Subscriber + Send + Sync {
-	FmtSubscriber::builder()
+fn default_subscriber(log_level: Level) -> (impl Subscriber + Send + Sync, LogLevels) {
+	let builder = FmtSubscriber::builder()
 		.with_env_filter(EnvFilter::new(format!("avail_light={log_level}")))
 		.with_span_events(format::FmtSpan::CLOSE)
-		.finish()
+		.with_filter_reloading();
+	let handle = builder.reload_handle();
+	let log_levels = LogLevels::new(log_level, move |filter| {
+		handle.reload(filter).map_err(|error| eyre!(error))
+	});
+	(builder.finish(), log_levels)
 }
 
 fn parse_log_level(log_level: &str, default: Level) -> (Level, Option<ParseLevelError>) {
@@ -78,13 +100,16 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 
 	let (log_level, parse_error) = parse_log_level(&cfg.log_level, Level::INFO);
 
-	if cfg.log_format_json {
-		tracing::subscriber::set_global_default(json_subscriber(log_level))
-			.expect("global json subscriber is set")
+	let log_levels = if cfg.log_format_json {
+		let (subscriber, log_levels) = json_subscriber(log_level);
+		tracing::subscriber::set_global_default(subscriber).expect("global json subscriber is set");
+		log_levels
 	} else {
-		tracing::subscriber::set_global_default(default_subscriber(log_level))
-			.expect("global default subscriber is set")
-	}
+		let (subscriber, log_levels) = default_subscriber(log_level);
+		tracing::subscriber::set_global_default(subscriber)
+			.expect("global default subscriber is set");
+		log_levels
+	};
 
 	let identity_cfg =
 		IdentityConfig::load_or_init(&opts.identity, opts.avail_passphrase.as_deref())?;
@@ -106,24 +131,27 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		warn!("Using default log level: {}", error);
 	}
 
+	cfg.validate().wrap_err("Invalid configuration")?;
+
 	if opts.clean && Path::new(&cfg.avail_path).exists() {
 		info!("Cleaning up local state directory");
 		fs::remove_dir_all(&cfg.avail_path).wrap_err("Failed to remove local state directory")?;
 	}
 
-	if cfg.bootstraps.is_empty() {
-		Err(eyre!("Bootstrap node list must not be empty. Either use a '--network' flag or add a list of bootstrap nodes in the configuration file"))?
-	}
-
 	let db =
 		RocksDB::open(&cfg.avail_path).wrap_err("Avail Light could not initialize database")?;
+	let indexed = data::backfill_block_hash_index(&db)
+		.wrap_err("Avail Light could not index stored block hashes")?;
+	if indexed > 0 {
+		info!("Indexed {indexed} stored block hashes");
+	}
 
 	let cfg_libp2p: LibP2PConfig = (&cfg).into();
 	let (id_keys, peer_id) = p2p::keypair(&cfg_libp2p)?;
 
 	let metric_attributes = MetricAttributes {
 		role: client_role.into(),
-		peer_id,
+		peer_id: peer_id.clone(),
 		ip: RwLock::new("".to_string()),
 		multiaddress: RwLock::new("".to_string()), // Default value is empty until first processed block triggers an update,
 		origin: cfg.origin.clone(),
@@ -150,37 +178,41 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 			.wrap_err("Unable to initialize OpenTelemetry service")?,
 	);
 
-	// Create sender channel for P2P event loop commands
-	let (p2p_event_loop_sender, p2p_event_loop_receiver) = mpsc::unbounded_channel();
-
-	let p2p_event_loop = p2p::EventLoop::new(
+	let (p2p_client, p2p_event_loop) = p2p::init(
 		cfg_libp2p,
 		&id_keys,
+		cfg.dht_parallelization_limit,
+		cfg.kad_record_ttl,
 		cfg.is_fat_client(),
 		cfg.ws_transport_enable,
 		shutdown.clone(),
-	);
+	)
+	.await?;
+	info!("Local peer ID: {}", p2p_event_loop.local_peer_id());
 
-	tokio::spawn(
-		shutdown.with_cancel(
-			p2p_event_loop
-				.await
-				.run(ot_metrics.clone(), p2p_event_loop_receiver),
-		),
-	);
+	tokio::spawn(shutdown.with_cancel(p2p_event_loop.run(ot_metrics.clone())));
 
-	let p2p_client = p2p::Client::new(
-		p2p_event_loop_sender,
-		cfg.dht_parallelization_limit,
-		cfg.kad_record_ttl,
-	);
-
-	// Start listening on provided port
-	p2p_client
-		.start_listening(construct_multiaddress(cfg.ws_transport_enable, cfg.port))
-		.await
-		.wrap_err("Listening on TCP not to fail.")?;
-	info!("TCP listener started on port {}", cfg.port);
+	// Start listening on provided port, unless connections go through the proxy
+	if let Some(proxy) = &cfg.socks5_proxy {
+		info!("Outbound connections go through SOCKS5 proxy {proxy}, listening is disabled");
+	} else {
+		let mut listen_addresses = vec![construct_multiaddress(cfg.ws_transport_enable, cfg.port)];
+		if cfg.quic_transport_enable && !cfg.ws_transport_enable {
+			listen_addresses.push(construct_quic_multiaddress(cfg.port));
+		}
+		let listen_results = p2p_client
+			.start_listening(listen_addresses)
+			.await
+			.wrap_err("Listening on TCP not to fail.")?;
+		for (index, (address, result)) in listen_results.into_iter().enumerate() {
+			match result {
+				Ok(()) => info!("Listener started on {address}"),
+				// Listening on the primary (TCP or WebSocket) address is required
+				Err(error) if index == 0 => Err(eyre!("Listening on {address} failed: {error}"))?,
+				Err(error) => warn!("Listening on {address} failed: {error}"),
+			}
+		}
+	}
 
 	let p2p_clone = p2p_client.to_owned();
 	let cfg_clone = cfg.to_owned();
@@ -215,15 +247,57 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		&cfg.full_node_ws,
 		&cfg.genesis_hash,
 		cfg.retry_config.clone(),
+		(&cfg).into(),
+		(&cfg).into(),
 	)
 	.await?;
 
+	let genesis_hash = state.lock().unwrap().connected_node.genesis_hash;
+	data::check_genesis_hash(&db, genesis_hash)
+		.wrap_err("Database cannot be used with the connected network")?;
+
+	// Persisted peers are used only once the database is known to belong to the connected network
+	if cfg.persist_peers {
+		let known_peers = load_known_peers(&db)?;
+		info!("Dialing {} persisted peers...", known_peers.len());
+		for (peer_id, addresses) in known_peers {
+			let p2p_client = p2p_client.clone();
+			tokio::spawn(shutdown.with_cancel(async move {
+				dial_known_peer(&p2p_client, peer_id, addresses).await
+			}));
+		}
+		tokio::spawn(shutdown.with_cancel(persist_known_peers(p2p_client.clone(), db.clone())));
+	}
+
+	let denied_peers = load_denied_peers(&db)?;
+	if !denied_peers.is_empty() {
+		info!("Denying {} persisted peers...", denied_peers.len());
+	}
+	for peer_id in denied_peers {
+		p2p_client
+			.forget_peer(peer_id)
+			.await
+			.wrap_err("Unable to deny persisted peer")?;
+	}
+	tokio::spawn(shutdown.with_cancel(persist_denied_peers(p2p_client.clone(), db.clone())));
+
+	let supervisor = Supervisor::new(rpc_events.clone(), shutdown.clone());
+
 	// Subscribing to RPC events before first event is published
 	let publish_rpc_event_receiver = rpc_events.subscribe();
 	let first_header_rpc_event_receiver = rpc_events.subscribe();
 	let client_rpc_event_receiver = rpc_events.subscribe();
 	#[cfg(feature = "crawl")]
 	let crawler_rpc_event_receiver = rpc_events.subscribe();
+	if let Some(path) = &cfg.event_recording_path {
+		let recording_rpc_event_receiver = rpc_events.subscribe();
+		tokio::spawn(shutdown.with_cancel(rpc::recording::record(
+			path.into(),
+			recording_rpc_event_receiver,
+		)));
+	}
+
+	let head_pin = rpc_subscriptions.head_pin();
 
 	// spawn the RPC Network task for Event Loop to run in the background
 	// and shut it down, without delays
@@ -281,7 +355,11 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		version: format!("v{}", clap::crate_version!()),
 		network_version: EXPECTED_SYSTEM_VERSION[0].to_string(),
 		node_client: rpc_client.clone(),
+		p2p_client: p2p_client.clone(),
+		peer_id,
 		ws_clients: ws_clients.clone(),
+		log_levels: Some(log_levels),
+		head_pin: Some(head_pin),
 		shutdown: shutdown.clone(),
 	};
 	tokio::task::spawn(shutdown.with_cancel(server.bind()));
@@ -339,35 +417,53 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		)));
 	}
 
-	let sync_client = SyncClient::new(db.clone(), rpc_client.clone());
-
-	let sync_network_client = network::new(
-		p2p_client.clone(),
-		rpc_client.clone(),
-		pp.clone(),
-		cfg.disable_rpc,
-	);
-
 	if cfg.sync_start_block.is_some() {
 		state.lock().unwrap().synced.replace(false);
-		tokio::task::spawn(shutdown.with_cancel(avail_light::sync_client::run(
-			sync_client,
-			sync_network_client,
-			(&cfg).into(),
-			sync_range,
-			block_tx.clone(),
-			state.clone(),
-		)));
+		let (db, p2p_client, rpc_client, pp) = (
+			db.clone(),
+			p2p_client.clone(),
+			rpc_client.clone(),
+			pp.clone(),
+		);
+		let (block_tx, state) = (block_tx.clone(), state.clone());
+		let sync_cfg: SyncClientConfig = (&cfg).into();
+		let disable_rpc = cfg.disable_rpc;
+		// Restarted sync client syncs the whole range again, skipping already stored blocks
+		supervisor.spawn_restartable(TaskKind::SyncClient, move || {
+			avail_light::sync_client::run(
+				SyncClient::new(db.clone(), rpc_client.clone()),
+				network::new(
+					p2p_client.clone(),
+					rpc_client.clone(),
+					pp.clone(),
+					disable_rpc,
+				),
+				sync_cfg.clone(),
+				sync_range.clone(),
+				block_tx.clone(),
+				state.clone(),
+			)
+		});
 	}
 
 	if cfg.sync_finality_enable {
+		let trusted_checkpoint = cfg
+			.sync_finality_trusted_authority_set
+			.as_ref()
+			.map(TryInto::try_into)
+			.transpose()
+			.wrap_err("Invalid trusted authority set")?;
 		let sync_finality = SyncFinality::new(db.clone(), rpc_client.clone());
-		tokio::task::spawn(shutdown.with_cancel(avail_light::sync_finality::run(
-			sync_finality,
-			shutdown.clone(),
-			state.clone(),
-			block_header.clone(),
-		)));
+		supervisor.spawn(
+			TaskKind::SyncFinality,
+			avail_light::sync_finality::run(
+				sync_finality,
+				shutdown.clone(),
+				state.clone(),
+				block_header.clone(),
+				trusted_checkpoint,
+			),
+		);
 	} else {
 		let mut s = state
 			.lock()
@@ -383,48 +479,179 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		pruning_interval: cfg.store_pruning_interval,
 	};
 
-	tokio::task::spawn(shutdown.with_cancel(avail_light::maintenance::run(
-		p2p_client.clone(),
-		ot_metrics.clone(),
-		block_rx,
-		static_config_params,
-		shutdown.clone(),
-	)));
+	supervisor.spawn(
+		TaskKind::Maintenance,
+		avail_light::maintenance::run(
+			p2p_client.clone(),
+			ot_metrics.clone(),
+			block_rx,
+			static_config_params,
+			rpc_events.clone(),
+			shutdown.clone(),
+		),
+	);
 
-	let channels = avail_light::types::ClientChannels {
-		block_sender: block_tx,
-		rpc_event_receiver: client_rpc_event_receiver,
+	// First run uses the receiver subscribed before the first event was published,
+	// restarted client subscribes again and continues from the latest event
+	let mut client_rpc_event_receiver = Some(client_rpc_event_receiver);
+	let events = rpc_events;
+	let mut new_channels = move || avail_light::types::ClientChannels {
+		block_sender: block_tx.clone(),
+		rpc_event_receiver: client_rpc_event_receiver
+			.take()
+			.unwrap_or_else(|| events.subscribe()),
 	};
 
 	if let Some(partition) = cfg.block_matrix_partition {
-		let fat_client = avail_light::fat_client::new(p2p_client.clone(), rpc_client.clone());
-
-		tokio::task::spawn(shutdown.with_cancel(avail_light::fat_client::run(
-			fat_client,
-			db.clone(),
-			(&cfg).into(),
-			ot_metrics.clone(),
-			channels,
-			partition,
-			shutdown.clone(),
-		)));
+		let shutdown_clone = shutdown.clone();
+		supervisor.spawn_restartable(TaskKind::FatClient, move || {
+			avail_light::fat_client::run(
+				avail_light::fat_client::new(p2p_client.clone(), rpc_client.clone()),
+				db.clone(),
+				(&cfg).into(),
+				ot_metrics.clone(),
+				new_channels(),
+				partition,
+				shutdown_clone.clone(),
+			)
+		});
 	} else {
-		let light_network_client = network::new(p2p_client, rpc_client, pp, cfg.disable_rpc);
-
-		tokio::task::spawn(shutdown.with_cancel(avail_light::light_client::run(
-			db.clone(),
-			light_network_client,
-			(&cfg).into(),
-			ot_metrics,
-			state.clone(),
-			channels,
-			shutdown.clone(),
-		)));
+		let (state, shutdown_clone) = (state.clone(), shutdown.clone());
+		supervisor.spawn_restartable(TaskKind::LightClient, move || {
+			avail_light::light_client::run(
+				db.clone(),
+				network::new(
+					p2p_client.clone(),
+					rpc_client.clone(),
+					pp.clone(),
+					cfg.disable_rpc,
+				),
+				(&cfg).into(),
+				ot_metrics.clone(),
+				state.clone(),
+				new_channels(),
+				shutdown_clone.clone(),
+			)
+		});
 	}
 
 	Ok(())
 }
 
+fn load_known_peers(db: &RocksDB) -> Result<Vec<(PeerId, Vec<Multiaddr>)>> {
+	let known_peers: Option<Vec<KnownPeer>> = db
+		.get(Key::KnownPeers)
+		.wrap_err("Unable to load known peers")?;
+
+	let known_peers = known_peers
+		.unwrap_or_default()
+		.into_iter()
+		.filter_map(|known_peer| {
+			let peer_id = known_peer.peer_id.parse().ok()?;
+			let addresses = known_peer
+				.addresses
+				.iter()
+				.filter_map(|address| address.parse().ok())
+				.collect();
+			Some((peer_id, addresses))
+		})
+		.collect();
+	Ok(known_peers)
+}
+
+async fn dial_known_peer(p2p_client: &p2p::Client, peer_id: PeerId, addresses: Vec<Multiaddr>) {
+	for address in addresses {
+		match p2p_client.dial_peer(peer_id, address.clone()).await {
+			Ok(()) => {
+				if let Err(error) = p2p_client.add_address(peer_id, address).await {
+					warn!("Cannot add address of persisted peer {peer_id}: {error:#}");
+				}
+				return;
+			},
+			Err(error) => debug!("Dialing persisted peer {peer_id} on {address} failed: {error:#}"),
+		}
+	}
+}
+
+async fn persist_known_peers(p2p_client: p2p::Client, db: RocksDB) {
+	let mut interval = interval_at(
+		Instant::now() + PERSIST_PEERS_INTERVAL,
+		PERSIST_PEERS_INTERVAL,
+	);
+	loop {
+		interval.tick().await;
+		let known_peers = match p2p_client.known_peers(MAX_PERSISTED_PEERS).await {
+			Ok(known_peers) => known_peers,
+			Err(error) => {
+				warn!("Cannot get known peers: {error:#}");
+				continue;
+			},
+		};
+		// Keep previously persisted peers if none are known at the moment
+		if known_peers.is_empty() {
+			continue;
+		}
+
+		let known_peers = known_peers
+			.into_iter()
+			.map(|(peer_id, addresses)| KnownPeer {
+				peer_id: peer_id.to_string(),
+				addresses: addresses.iter().map(ToString::to_string).collect(),
+			})
+			.collect::<Vec<_>>();
+		trace!("Persisting {} known peers", known_peers.len());
+		if let Err(error) = db.put(Key::KnownPeers, known_peers) {
+			warn!("Cannot persist known peers: {error:#}");
+		}
+	}
+}
+
+fn load_denied_peers(db: &RocksDB) -> Result<Vec<PeerId>> {
+	let denied_peers: Option<Vec<String>> = db
+		.get(Key::DeniedPeers)
+		.wrap_err("Unable to load denied peers")?;
+
+	let denied_peers = denied_peers
+		.unwrap_or_default()
+		.iter()
+		.filter_map(|peer_id| peer_id.parse().ok())
+		.collect();
+	Ok(denied_peers)
+}
+
+async fn persist_denied_peers(p2p_client: p2p::Client, db: RocksDB) {
+	let mut interval = interval_at(
+		Instant::now() + PERSIST_PEERS_INTERVAL,
+		PERSIST_PEERS_INTERVAL,
+	);
+	let mut persisted: Option<Vec<String>> = None;
+	loop {
+		interval.tick().await;
+		let denied_peers = match p2p_client.deny_list().await {
+			Ok(denied_peers) => denied_peers,
+			Err(error) => {
+				warn!("Cannot get denied peers: {error:#}");
+				continue;
+			},
+		};
+
+		// Cleared deny list is persisted as well, so the peers are not denied again on the next startup
+		let mut denied_peers = denied_peers
+			.iter()
+			.map(ToString::to_string)
+			.collect::<Vec<_>>();
+		denied_peers.sort();
+		if persisted.as_ref() == Some(&denied_peers) {
+			continue;
+		}
+		trace!("Persisting {} denied peers", denied_peers.len());
+		match db.put(Key::DeniedPeers, denied_peers.clone()) {
+			Ok(()) => persisted = Some(denied_peers),
+			Err(error) => warn!("Cannot persist denied peers: {error:#}"),
+		}
+	}
+}
+
 fn construct_multiaddress(is_websocket: bool, port: u16) -> Multiaddr {
 	let tcp_multiaddress = Multiaddr::empty()
 		.with(Protocol::from(Ipv4Addr::UNSPECIFIED))
@@ -437,34 +664,11 @@ fn construct_multiaddress(is_websocket: bool, port: u16) -> Multiaddr {
 	tcp_multiaddress
 }
 
-fn install_panic_hooks(shutdown: Controller<String>) -> Result<()> {
-	// initialize color-eyre hooks
-	let (panic_hook, eyre_hook) = color_eyre::config::HookBuilder::default()
-		.display_location_section(true)
-		.display_env_section(true)
-		.into_hooks();
-
-	// install hook as global handler
-	eyre_hook.install()?;
-
-	std::panic::set_hook(Box::new(move |panic_info| {
-		// trigger shutdown to stop other tasks if panic occurs
-		let _ = shutdown.trigger_shutdown("Panic occurred, shuting down".to_string());
-
-		let msg = format!("{}", panic_hook.panic_report(panic_info));
-		error!("Error: {}", strip_ansi_escapes::strip_str(msg));
-
-		#[cfg(debug_assertions)]
-		{
-			// better-panic stacktrace that is only enabled when debugging
-			better_panic::Settings::auto()
-				.most_recent_first(false)
-				.lineno_suffix(true)
-				.verbosity(better_panic::Verbosity::Medium)
-				.create_panic_handler()(panic_info);
-		}
-	}));
-	Ok(())
+fn construct_quic_multiaddress(port: u16) -> Multiaddr {
+	Multiaddr::empty()
+		.with(Protocol::from(Ipv4Addr::UNSPECIFIED))
+		.with(Protocol::Udp(port))
+		.with(Protocol::QuicV1)
 }
 
 /// This utility function returns a [`Future`] that completes upon
@@ -514,7 +718,7 @@ pub async fn main() -> Result<()> {
 	let shutdown = Controller::new();
 
 	// install custom panic hooks
-	install_panic_hooks(shutdown.clone())?;
+	supervisor::install_panic_hooks(shutdown.clone())?;
 
 	// spawn a task to watch for ctrl-c signals from user to trigger the shutdown
 	tokio::spawn(shutdown.with_trigger("user signaled shutdown".to_string(), user_signal()));
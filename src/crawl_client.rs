@@ -65,11 +65,20 @@ pub async fn run(
 
 	let delay = Delay(Some(Duration::from_secs(delay)));
 
-	while let Ok(rpc::Event::HeaderUpdate {
-		header,
-		received_at,
-	}) = message_rx.recv().await
-	{
+	loop {
+		let (header, received_at) = match message_rx.recv().await {
+			Ok(rpc::Event::HeaderUpdate {
+				header,
+				received_at,
+				..
+			}) => (header, received_at),
+			Ok(_) => continue,
+			Err(error) => {
+				error!("Cannot receive message: {error}");
+				return;
+			},
+		};
+
 		let block = match types::BlockVerified::try_from((header, None)) {
 			Ok(block) => block,
 			Err(error) => {
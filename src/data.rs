@@ -1,7 +1,11 @@
-use codec::{Decode, Encode};
-use color_eyre::eyre::Result;
+use avail_subxt::{primitives::Header, utils::H256};
+use codec::{Decode, Encode, IoReader};
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use futures::{stream, Stream, TryStreamExt};
 use serde::{Deserialize, Serialize};
-use sp_core::ed25519;
+use sp_core::{blake2_256, ed25519};
+use std::io::{BufRead, BufReader, Read, Write};
+use tracing::warn;
 
 pub mod rocks_db;
 
@@ -26,6 +30,10 @@ pub trait Database {
 
 	/// Deletes value from the database for the given key.
 	fn delete(&self, key: Key) -> Result<()>;
+
+	/// Gets the lowest and the highest block number with stored block header.
+	/// Returns `None` if there are no stored headers.
+	fn stored_block_range(&self) -> Result<Option<(u32, u32)>>;
 }
 
 /// Column family for confidence factor
@@ -34,6 +42,9 @@ pub const CONFIDENCE_FACTOR_CF: &str = "avail_light_confidence_factor_cf";
 /// Column family for block header
 pub const BLOCK_HEADER_CF: &str = "avail_light_block_header_cf";
 
+/// Column family for block number by block hash index
+pub const BLOCK_HASH_CF: &str = "avail_light_block_hash_cf";
+
 /// Column family for app data
 pub const APP_DATA_CF: &str = "avail_light_app_data_cf";
 
@@ -43,12 +54,32 @@ pub const STATE_CF: &str = "avail_light_state_cf";
 /// Sync finality checkpoint key name
 const FINALITY_SYNC_CHECKPOINT_KEY: &str = "finality_sync_checkpoint";
 
+/// Known peers key name
+const KNOWN_PEERS_KEY: &str = "known_peers";
+
+/// Denied peers key name
+const DENIED_PEERS_KEY: &str = "denied_peers";
+
+/// Genesis hash key name
+const GENESIS_HASH_KEY: &str = "genesis_hash";
+
 #[derive(Clone)]
 pub enum Key {
 	AppData(u32, u32),
 	BlockHeader(u32),
+	BlockNumber(H256),
 	VerifiedCellCount(u32),
 	FinalitySyncCheckpoint,
+	KnownPeers,
+	DeniedPeers,
+	GenesisHash,
+}
+
+/// Peer ID and addresses of a peer, persisted to be dialed on the next startup
+#[derive(Serialize, Deserialize, Debug, Decode, Encode)]
+pub struct KnownPeer {
+	pub peer_id: String,
+	pub addresses: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Decode, Encode)]
@@ -57,3 +88,341 @@ pub struct FinalitySyncCheckpoint {
 	pub set_id: u64,
 	pub validator_set: Vec<ed25519::Public>,
 }
+
+fn header_hash(header: &Header) -> H256 {
+	Encode::using_encoded(header, blake2_256).into()
+}
+
+/// Stores block header, indexed by both block number and block hash.
+pub fn store_block_header<T: Database>(db: &T, block_number: u32, header: &Header) -> Result<()> {
+	db.put(Key::BlockHeader(block_number), header)?;
+	db.put(Key::BlockNumber(header_hash(header)), block_number)
+}
+
+/// Gets stored block header by block hash.
+/// Header of the block which has the same number as the stored one is not returned.
+pub fn get_block_header_by_hash<T: Database>(db: &T, block_hash: H256) -> Result<Option<Header>> {
+	let Some(block_number) = db.get::<u32>(Key::BlockNumber(block_hash))? else {
+		return Ok(None);
+	};
+	let header = db
+		.get::<Header>(Key::BlockHeader(block_number))?
+		.filter(|header| header_hash(header) == block_hash);
+	Ok(header)
+}
+
+/// Indexes block hashes of the headers stored before the block hash index existed.
+/// Headers are indexed from the highest stored block down, so the index is complete once the lowest stored header is indexed,
+/// which makes subsequent calls cheap. Returns the number of newly indexed headers.
+pub fn backfill_block_hash_index<T: Database>(db: &T) -> Result<u32> {
+	let Some((first, last)) = db.stored_block_range()? else {
+		return Ok(0);
+	};
+	let is_indexed = |header: &Header| -> Result<bool> {
+		Ok(db
+			.get::<u32>(Key::BlockNumber(header_hash(header)))?
+			.is_some())
+	};
+	match db.get::<Header>(Key::BlockHeader(first))? {
+		Some(header) if is_indexed(&header)? => return Ok(0),
+		_ => (),
+	}
+
+	let mut indexed = 0;
+	for block_number in (first..=last).rev() {
+		let Some(header) = db.get::<Header>(Key::BlockHeader(block_number))? else {
+			continue;
+		};
+		if !is_indexed(&header)? {
+			db.put(Key::BlockNumber(header_hash(&header)), block_number)?;
+			indexed += 1;
+		}
+	}
+	Ok(indexed)
+}
+
+/// Streams stored headers from the given block number up to the highest stored block,
+/// as block number, block hash and decoded header.
+/// Headers are read from the database one at a time, and missing headers are skipped.
+pub fn decoded_headers_since<T: Database>(
+	db: T,
+	from: u32,
+) -> Result<impl Stream<Item = Result<(u32, H256, Header)>>> {
+	let block_numbers = match db.stored_block_range()? {
+		Some((_, last)) => from..=last,
+		// Empty range
+		None => 1..=0,
+	};
+
+	let headers = block_numbers.filter_map(move |block_number| {
+		match db.get::<Header>(Key::BlockHeader(block_number)) {
+			Ok(None) => None,
+			Ok(Some(header)) => {
+				let hash = header_hash(&header);
+				Some(Ok((block_number, hash, header)))
+			},
+			Err(error) => Some(Err(error)),
+		}
+	});
+	Ok(stream::iter(headers))
+}
+
+/// Streams stored headers like [`decoded_headers_since`], with SCALE encoded headers.
+pub fn finalized_headers_since<T: Database>(
+	db: T,
+	from: u32,
+) -> Result<impl Stream<Item = Result<(u32, H256, Vec<u8>)>>> {
+	let headers = decoded_headers_since(db, from)?;
+	Ok(headers.map_ok(|(block_number, hash, header)| (block_number, hash, header.encode())))
+}
+
+/// Stores the genesis hash on the first use of the database, and checks it on subsequent uses,
+/// so the database of one network is not mixed with the data of another.
+pub fn check_genesis_hash<T: Database>(db: &T, genesis_hash: H256) -> Result<()> {
+	match db.get::<H256>(Key::GenesisHash)? {
+		None => db.put(Key::GenesisHash, genesis_hash),
+		Some(stored) if stored == genesis_hash => Ok(()),
+		Some(stored) => Err(eyre!(
+			"Database belongs to the network with genesis hash {stored:?}, but connected network has genesis hash {genesis_hash:?}"
+		)),
+	}
+}
+
+/// Exports stored headers in the given block range (inclusive) as concatenated SCALE encoded headers,
+/// in the format consumed by [`import_trusted_headers`]. Headers are read and written one at a time.
+/// Missing headers (e.g. not synced or pruned) are skipped and reported.
+/// Returns the number of written headers.
+pub fn export_headers<T: Database>(
+	db: &T,
+	from: u32,
+	to: u32,
+	mut writer: impl Write,
+) -> Result<u32> {
+	let mut exported = 0;
+	let mut missing = 0;
+	for block_number in from..=to {
+		let Some(header) = db.get::<Header>(Key::BlockHeader(block_number))? else {
+			missing += 1;
+			continue;
+		};
+		writer
+			.write_all(&header.encode())
+			.wrap_err_with(|| format!("Cannot write header {block_number}"))?;
+		exported += 1;
+	}
+	writer.flush().wrap_err("Cannot flush exported headers")?;
+
+	if missing > 0 {
+		warn!(
+			from,
+			to, missing, "Headers are missing in the exported range"
+		);
+	}
+	Ok(exported)
+}
+
+/// Imports SCALE encoded headers from a trusted source, concatenated in ascending order, e.g. exported by another light client.
+/// Headers bypass the finality verification pipeline, so they must not come from an untrusted source.
+/// Each header has to be the child of the previous one (or of the stored header preceding it), and stored headers are not replaced.
+/// Import stops with an error naming the first header which doesn't connect, or which conflicts with the stored one,
+/// while headers stored before it are kept. Returns the number of newly stored headers.
+pub fn import_trusted_headers<T: Database>(db: &T, reader: impl Read) -> Result<u32> {
+	let mut reader = BufReader::new(reader);
+	let mut parent: Option<(u32, H256)> = None;
+	let mut imported = 0;
+
+	while !reader
+		.fill_buf()
+		.wrap_err("Cannot read headers")?
+		.is_empty()
+	{
+		let header = Header::decode(&mut IoReader(&mut reader))
+			.map_err(|error| eyre!("Cannot decode header: {error}"))?;
+		let number = header.number;
+		let hash = header_hash(&header);
+
+		if parent.is_none() && number > 0 {
+			parent = db
+				.get::<Header>(Key::BlockHeader(number - 1))?
+				.map(|parent| (number - 1, header_hash(&parent)));
+		}
+		if let Some((parent_number, parent_hash)) = parent {
+			if number != parent_number + 1 || header.parent_hash != parent_hash {
+				return Err(eyre!(
+					"Cannot import block {number}: not the child of block {parent_number} ({imported} headers imported)"
+				));
+			}
+		}
+
+		match db.get::<Header>(Key::BlockHeader(number))? {
+			Some(stored) if header_hash(&stored) != hash => {
+				return Err(eyre!(
+					"Cannot import block {number}: conflicts with the stored header ({imported} headers imported)"
+				));
+			},
+			Some(_) => (),
+			None => {
+				store_block_header(db, number, &header)?;
+				imported += 1;
+			},
+		}
+		parent = Some((number, hash));
+	}
+	Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		backfill_block_hash_index, check_genesis_hash, export_headers, get_block_header_by_hash,
+		header_hash, import_trusted_headers, mem_db::MemoryDB, store_block_header, Database, Key,
+	};
+	use avail_subxt::{
+		api::runtime_types::avail_core::{
+			data_lookup::compact::CompactDataLookup,
+			header::extension::{v3, HeaderExtension},
+			kate_commitment::v3::KateCommitment,
+		},
+		primitives::Header,
+		utils::H256,
+	};
+	use codec::Encode;
+	use subxt::config::substrate::Digest;
+
+	fn headers(from: u32, count: u32) -> Vec<Header> {
+		let mut parent_hash = H256::default();
+		(from..from + count)
+			.map(|number| {
+				let header = Header {
+					parent_hash,
+					number,
+					state_root: H256::default(),
+					extrinsics_root: H256::default(),
+					extension: HeaderExtension::V3(v3::HeaderExtension {
+						commitment: KateCommitment::default(),
+						app_lookup: CompactDataLookup {
+							size: 0,
+							index: vec![],
+						},
+					}),
+					digest: Digest { logs: vec![] },
+				};
+				parent_hash = header_hash(&header);
+				header
+			})
+			.collect()
+	}
+
+	#[test]
+	fn backfill_block_hash_index_once() {
+		let db = MemoryDB::default();
+		let headers = headers(1, 3);
+		// Headers stored without the block hash index
+		for header in &headers[..2] {
+			db.put(Key::BlockHeader(header.number), header).unwrap();
+		}
+		store_block_header(&db, 3, &headers[2]).unwrap();
+		assert!(get_block_header_by_hash(&db, header_hash(&headers[0]))
+			.unwrap()
+			.is_none());
+
+		assert_eq!(backfill_block_hash_index(&db).unwrap(), 2);
+		for header in &headers {
+			let stored = get_block_header_by_hash(&db, header_hash(header)).unwrap();
+			assert_eq!(stored.map(|header| header.number), Some(header.number));
+		}
+		assert_eq!(backfill_block_hash_index(&db).unwrap(), 0);
+	}
+
+	#[test]
+	fn import_connected_headers() {
+		let db = MemoryDB::default();
+		let headers = headers(1, 3);
+		let encoded = headers.iter().flat_map(Encode::encode).collect::<Vec<u8>>();
+
+		assert_eq!(import_trusted_headers(&db, &encoded[..]).unwrap(), 3);
+		assert_eq!(db.stored_block_range().unwrap(), Some((1, 3)));
+		let stored = db.get::<Header>(Key::BlockHeader(2)).unwrap().unwrap();
+		assert_eq!(header_hash(&stored), header_hash(&headers[1]));
+
+		// Already stored headers are skipped
+		assert_eq!(import_trusted_headers(&db, &encoded[..]).unwrap(), 0);
+	}
+
+	#[test]
+	fn import_disconnected_headers() {
+		let db = MemoryDB::default();
+		let mut headers = headers(1, 3);
+		headers.remove(1);
+		let encoded = headers.iter().flat_map(Encode::encode).collect::<Vec<u8>>();
+
+		// Import fails at the disconnected header, while the ones stored before it are kept
+		let error = import_trusted_headers(&db, &encoded[..]).unwrap_err();
+		assert!(error.to_string().contains("block 3"));
+		assert_eq!(db.stored_block_range().unwrap(), Some((1, 1)));
+	}
+
+	#[test]
+	fn import_conflicting_headers() {
+		let db = MemoryDB::default();
+		let headers = headers(1, 3);
+		let mut conflicting = headers[1].clone();
+		conflicting.state_root = H256::repeat_byte(1);
+		store_block_header(&db, 2, &conflicting).unwrap();
+		let encoded = headers.iter().flat_map(Encode::encode).collect::<Vec<u8>>();
+
+		let error = import_trusted_headers(&db, &encoded[..]).unwrap_err();
+		assert!(error.to_string().contains("block 2"));
+		let stored = db.get::<Header>(Key::BlockHeader(2)).unwrap().unwrap();
+		assert_eq!(header_hash(&stored), header_hash(&conflicting));
+	}
+
+	#[test]
+	fn export_and_import_headers() {
+		let db = MemoryDB::default();
+		for header in headers(1, 4) {
+			store_block_header(&db, header.number, &header).unwrap();
+		}
+		db.delete(Key::BlockHeader(4)).unwrap();
+
+		let mut exported = vec![];
+		assert_eq!(export_headers(&db, 1, 5, &mut exported).unwrap(), 3);
+
+		let imported_db = MemoryDB::default();
+		assert_eq!(
+			import_trusted_headers(&imported_db, &exported[..]).unwrap(),
+			3
+		);
+		assert_eq!(imported_db.stored_block_range().unwrap(), Some((1, 3)));
+	}
+
+	#[test]
+	fn genesis_hash_mismatch() {
+		let db = MemoryDB::default();
+		check_genesis_hash(&db, H256::repeat_byte(1)).unwrap();
+		check_genesis_hash(&db, H256::repeat_byte(1)).unwrap();
+		assert!(check_genesis_hash(&db, H256::repeat_byte(2)).is_err());
+	}
+
+	#[test]
+	fn block_header_by_hash() {
+		let db = MemoryDB::default();
+		let headers = headers(1, 2);
+		for header in &headers {
+			store_block_header(&db, header.number, header).unwrap();
+		}
+
+		let hash = header_hash(&headers[1]);
+		let stored = get_block_header_by_hash(&db, hash).unwrap().unwrap();
+		assert_eq!(header_hash(&stored), hash);
+		assert!(get_block_header_by_hash(&db, H256::repeat_byte(1))
+			.unwrap()
+			.is_none());
+
+		// Header with the same number replaces the indexed one
+		let mut replaced = headers[1].clone();
+		replaced.state_root = H256::repeat_byte(1);
+		db.put(Key::BlockHeader(2), &replaced).unwrap();
+		assert!(get_block_header_by_hash(&db, hash).unwrap().is_none());
+	}
+}
@@ -1,5 +1,6 @@
 use crate::data::{
-	Database, Key, APP_DATA_CF, BLOCK_HEADER_CF, CONFIDENCE_FACTOR_CF, FINALITY_SYNC_CHECKPOINT_KEY,
+	Database, Key, APP_DATA_CF, BLOCK_HASH_CF, BLOCK_HEADER_CF, CONFIDENCE_FACTOR_CF,
+	DENIED_PEERS_KEY, FINALITY_SYNC_CHECKPOINT_KEY, GENESIS_HASH_KEY, KNOWN_PEERS_KEY,
 };
 use color_eyre::eyre::{eyre, Result};
 use serde::{Deserialize, Serialize};
@@ -51,6 +52,20 @@ impl Database for MemoryDB {
 		map.remove(&key.into());
 		Ok(())
 	}
+
+	fn stored_block_range(&self) -> Result<Option<(u32, u32)>> {
+		let map = self.map.read().expect("Lock acquired");
+		let prefix = format!("{BLOCK_HEADER_CF}:");
+		let block_numbers = map
+			.keys()
+			.filter_map(|HashMapKey(key)| key.strip_prefix(&prefix)?.parse::<u32>().ok())
+			.collect::<Vec<_>>();
+		let (Some(first), Some(last)) = (block_numbers.iter().min(), block_numbers.iter().max())
+		else {
+			return Ok(None);
+		};
+		Ok(Some((*first, *last)))
+	}
 }
 
 impl From<Key> for HashMapKey {
@@ -62,10 +77,14 @@ impl From<Key> for HashMapKey {
 			Key::BlockHeader(block_number) => {
 				HashMapKey(format!("{BLOCK_HEADER_CF}:{block_number}"))
 			},
+			Key::BlockNumber(block_hash) => HashMapKey(format!("{BLOCK_HASH_CF}:{block_hash:?}")),
 			Key::VerifiedCellCount(block_number) => {
 				HashMapKey(format!("{CONFIDENCE_FACTOR_CF}:{block_number}"))
 			},
 			Key::FinalitySyncCheckpoint => HashMapKey(FINALITY_SYNC_CHECKPOINT_KEY.to_string()),
+			Key::KnownPeers => HashMapKey(KNOWN_PEERS_KEY.to_string()),
+			Key::DeniedPeers => HashMapKey(DENIED_PEERS_KEY.to_string()),
+			Key::GenesisHash => HashMapKey(GENESIS_HASH_KEY.to_string()),
 		}
 	}
 }
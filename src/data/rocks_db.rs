@@ -1,11 +1,13 @@
-use crate::data::{self, Key, APP_DATA_CF, BLOCK_HEADER_CF, CONFIDENCE_FACTOR_CF, STATE_CF};
+use crate::data::{
+	self, Key, APP_DATA_CF, BLOCK_HASH_CF, BLOCK_HEADER_CF, CONFIDENCE_FACTOR_CF, STATE_CF,
+};
 use codec::{Decode, Encode};
 use color_eyre::eyre::{eyre, Context, Result};
-use rocksdb::{ColumnFamilyDescriptor, Options};
+use rocksdb::{ColumnFamilyDescriptor, IteratorMode, Options};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use super::FINALITY_SYNC_CHECKPOINT_KEY;
+use super::{DENIED_PEERS_KEY, FINALITY_SYNC_CHECKPOINT_KEY, GENESIS_HASH_KEY, KNOWN_PEERS_KEY};
 
 #[derive(Clone)]
 pub struct RocksDB {
@@ -17,6 +19,7 @@ impl RocksDB {
 		let cf_opts = vec![
 			ColumnFamilyDescriptor::new(CONFIDENCE_FACTOR_CF, Options::default()),
 			ColumnFamilyDescriptor::new(BLOCK_HEADER_CF, Options::default()),
+			ColumnFamilyDescriptor::new(BLOCK_HASH_CF, Options::default()),
 			ColumnFamilyDescriptor::new(APP_DATA_CF, Options::default()),
 			ColumnFamilyDescriptor::new(STATE_CF, Options::default()),
 		];
@@ -42,6 +45,7 @@ impl From<Key> for (Option<&'static str>, Vec<u8>) {
 			Key::BlockHeader(block_number) => {
 				(Some(BLOCK_HEADER_CF), block_number.to_be_bytes().to_vec())
 			},
+			Key::BlockNumber(block_hash) => (Some(BLOCK_HASH_CF), block_hash.as_bytes().to_vec()),
 			Key::VerifiedCellCount(block_number) => (
 				Some(CONFIDENCE_FACTOR_CF),
 				block_number.to_be_bytes().to_vec(),
@@ -50,6 +54,9 @@ impl From<Key> for (Option<&'static str>, Vec<u8>) {
 				Some(STATE_CF),
 				FINALITY_SYNC_CHECKPOINT_KEY.as_bytes().to_vec(),
 			),
+			Key::KnownPeers => (Some(STATE_CF), KNOWN_PEERS_KEY.as_bytes().to_vec()),
+			Key::DeniedPeers => (Some(STATE_CF), DENIED_PEERS_KEY.as_bytes().to_vec()),
+			Key::GenesisHash => (Some(STATE_CF), GENESIS_HASH_KEY.as_bytes().to_vec()),
 		}
 	}
 }
@@ -126,4 +133,31 @@ impl data::Database for RocksDB {
 			.delete_cf(&cf_handle, key)
 			.wrap_err("Delete operation with Column Family failed on RocksDB")
 	}
+
+	fn stored_block_range(&self) -> Result<Option<(u32, u32)>> {
+		let cf_handle = self
+			.db
+			.cf_handle(BLOCK_HEADER_CF)
+			.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+
+		// Block headers are keyed by big-endian block number, so first and last keys are the range bounds
+		let block_number = |mode| -> Result<Option<u32>> {
+			let Some((key, _)) = self.db.iterator_cf(&cf_handle, mode).next().transpose()? else {
+				return Ok(None);
+			};
+			let key: [u8; 4] = key
+				.as_ref()
+				.try_into()
+				.wrap_err("Invalid block header key in RocksDB")?;
+			Ok(Some(u32::from_be_bytes(key)))
+		};
+
+		let (Some(first), Some(last)) = (
+			block_number(IteratorMode::Start)?,
+			block_number(IteratorMode::End)?,
+		) else {
+			return Ok(None);
+		};
+		Ok(Some((first, last)))
+	}
 }
@@ -25,7 +25,7 @@ use std::{sync::Arc, time::Instant};
 use tracing::{debug, error, info, warn};
 
 use crate::{
-	data::{Database, Key},
+	data::{store_block_header, Database},
 	network::{
 		p2p::Client as P2pClient,
 		rpc::{Client as RpcClient, Event},
@@ -113,7 +113,7 @@ pub async fn process_block(
 	// another competing thread, which syncs all block headers
 	// in range [0, LATEST], where LATEST = latest block number
 	// when this process started
-	db.put(Key::BlockHeader(block_number), header)
+	store_block_header(&db, block_number, header)
 		.wrap_err("Fat Client failed to store Block Header")?;
 
 	// Fat client partition upload logic
@@ -212,7 +212,9 @@ pub async fn run(
 				Event::HeaderUpdate {
 					header,
 					received_at,
+					..
 				} => (header, received_at),
+				_ => continue,
 			},
 			Err(error) => {
 				error!("Cannot receive message: {error}");
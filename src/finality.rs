@@ -1,15 +1,18 @@
-use std::collections::HashMap;
+use std::{
+	collections::HashMap,
+	fmt::{self, Display, Formatter},
+};
 
 use codec::Encode;
+use serde::{Deserialize, Serialize};
 use sp_core::{
 	blake2_256,
 	ed25519::{self, Public},
 	Pair, H256,
 };
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 
 use crate::types::{GrandpaJustification, SignerMessage};
-use color_eyre::{eyre::eyre, Result};
 
 #[derive(Clone, Debug)]
 pub struct ValidatorSet {
@@ -17,10 +20,41 @@ pub struct ValidatorSet {
 	pub validator_set: Vec<Public>,
 }
 
+/// Reason why the justification has failed verification
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "kind")]
+pub enum JustificationRejection {
+	/// Precommit signature doesn't verify with the current set ID, nor with the nearby ones
+	BadSignature { signer: String, set_id: u64 },
+	/// Precommit target is not the descendant of the justification target
+	UnknownAncestry { signer: String },
+	/// Not enough precommits are signed by the validators of the current set
+	InsufficientSignatures { signed: usize, required: usize },
+}
+
+impl Display for JustificationRejection {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			JustificationRejection::BadSignature { signer, set_id } => {
+				write!(f, "Invalid signature of {signer} for set_id {set_id}")
+			},
+			JustificationRejection::UnknownAncestry { signer } => {
+				write!(f, "Precommit of {signer} is not a descendant of the target")
+			},
+			JustificationRejection::InsufficientSignatures { signed, required } => write!(
+				f,
+				"Not signed by supermajority of validator set ({signed}/{required})"
+			),
+		}
+	}
+}
+
+impl std::error::Error for JustificationRejection {}
+
 pub fn check_finality(
 	validator_set: &ValidatorSet,
 	justification: &GrandpaJustification,
-) -> Result<()> {
+) -> Result<(), JustificationRejection> {
 	let ancestry_map: HashMap<H256, H256> = justification
 		.votes_ancestries
 		.iter()
@@ -75,18 +109,21 @@ pub fn check_finality(
 				&justification.commit.target_hash,
 				&ancestry_map,
 			);
-			(is_ok && ancestry)
-				.then(|| precommit.clone().id)
-				.ok_or_else(|| {
-					eyre!(
-				"Not signed by this signature! Sig id: {:?}, set_id: {}, justification: {:?}",
-				&precommit.id,
-				validator_set.set_id,
-				justification
-			)
-				})
+			if !is_ok {
+				debug!("Invalid precommit signature, justification: {justification:?}");
+				return Err(JustificationRejection::BadSignature {
+					signer: hex::encode(precommit.id.0),
+					set_id: validator_set.set_id,
+				});
+			}
+			if !ancestry {
+				return Err(JustificationRejection::UnknownAncestry {
+					signer: hex::encode(precommit.id.0),
+				});
+			}
+			Ok(precommit.clone().id)
 		})
-		.collect::<Result<Vec<_>>>();
+		.collect::<Result<Vec<_>, _>>();
 
 	// match all the Signer addresses to the Current Validator Set
 	let num_matched_addresses = signer_addresses?
@@ -101,14 +138,21 @@ pub fn check_finality(
 		validator_set.set_id
 	);
 
-	is_signed_by_supermajority(num_matched_addresses, validator_set.validator_set.len())
+	let validator_set_size = validator_set.validator_set.len();
+	is_signed_by_supermajority(num_matched_addresses, validator_set_size)
 		.then_some(())
-		.ok_or(eyre!("Not signed by supermajority of validator set!"))
+		.ok_or(JustificationRejection::InsufficientSignatures {
+			signed: num_matched_addresses,
+			required: supermajority(validator_set_size),
+		})
+}
+
+fn supermajority(validator_set_size: usize) -> usize {
+	(validator_set_size * 2 / 3) + 1
 }
 
 fn is_signed_by_supermajority(num_signatures: usize, validator_set_size: usize) -> bool {
-	let supermajority = (validator_set_size * 2 / 3) + 1;
-	num_signatures >= supermajority
+	num_signatures >= supermajority(validator_set_size)
 }
 
 fn confirm_ancestry(
@@ -147,7 +191,63 @@ mod tests {
 	};
 	use test_case::test_case;
 
-	use crate::types::{Precommit, SignerMessage};
+	use super::{check_finality, JustificationRejection, ValidatorSet};
+	use crate::types::{Commit, GrandpaJustification, Precommit, SignedPrecommit, SignerMessage};
+
+	fn justification(signer: &ed25519::Pair, set_id: u64) -> GrandpaJustification {
+		let precommit = Precommit {
+			target_hash: [1u8; 32].into(),
+			target_number: 10,
+		};
+		let signed_message = Encode::encode(&(
+			&SignerMessage::PrecommitMessage(precommit.clone()),
+			&1u64,
+			&set_id,
+		));
+		GrandpaJustification {
+			round: 1,
+			commit: Commit {
+				target_hash: precommit.target_hash,
+				target_number: precommit.target_number,
+				precommits: vec![SignedPrecommit {
+					signature: signer.sign(&signed_message),
+					id: signer.public(),
+					precommit,
+				}],
+			},
+			votes_ancestries: vec![],
+		}
+	}
+
+	#[test]
+	fn justification_rejection_reasons() {
+		let validator = ed25519::Pair::from_seed(&[1u8; 32]);
+		let other = ed25519::Pair::from_seed(&[2u8; 32]);
+		let valset = ValidatorSet {
+			set_id: 50,
+			validator_set: vec![validator.public()],
+		};
+
+		assert_eq!(
+			check_finality(&valset, &justification(&validator, 50)),
+			Ok(())
+		);
+		assert_eq!(
+			check_finality(&valset, &justification(&other, 50)),
+			Err(JustificationRejection::InsufficientSignatures {
+				signed: 0,
+				required: 1
+			})
+		);
+		assert_eq!(
+			check_finality(&valset, &justification(&validator, 100)),
+			Err(JustificationRejection::BadSignature {
+				signer: hex::encode(validator.public().0),
+				set_id: 50
+			})
+		);
+	}
+
 	#[test_case(1, 1 => true)]
 	#[test_case(1, 2 => false)]
 	#[test_case(2, 2 => true)]
@@ -7,10 +7,12 @@ pub mod data;
 pub mod fat_client;
 pub mod finality;
 pub mod light_client;
+pub mod logging;
 pub mod maintenance;
 pub mod network;
 pub mod proof;
 pub mod shutdown;
+pub mod supervisor;
 pub mod sync_client;
 pub mod sync_finality;
 pub mod telemetry;
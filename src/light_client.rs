@@ -29,7 +29,7 @@ use std::{
 use tracing::{error, info};
 
 use crate::{
-	data::{Database, Key},
+	data::{store_block_header, Database, Key},
 	network::{
 		self,
 		rpc::{self, Event},
@@ -154,7 +154,7 @@ pub async fn process_block(
 	// another competing thread, which syncs all block headers
 	// in range [0, LATEST], where LATEST = latest block number
 	// when this process started
-	db.put(Key::BlockHeader(block_number), header)
+	store_block_header(&db, block_number, &header)
 		.wrap_err("Light Client failed to store Block Header")?;
 
 	Ok(Some(confidence))
@@ -187,7 +187,9 @@ pub async fn run(
 				Event::HeaderUpdate {
 					header,
 					received_at,
+					..
 				} => (header, received_at),
+				_ => continue,
 			},
 			Err(error) => {
 				error!("Cannot receive message: {error}");
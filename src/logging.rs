@@ -0,0 +1,111 @@
+//! Runtime control of the log levels, per light client task.
+
+use color_eyre::{eyre::eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::HashMap,
+	fmt::{self, Display, Formatter},
+	sync::{Arc, Mutex},
+};
+use tracing::{info, level_filters::LevelFilter, Level};
+use tracing_subscriber::EnvFilter;
+
+/// Light client tasks with separately adjustable log levels
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum TaskKind {
+	LightClient,
+	AppClient,
+	FatClient,
+	SyncClient,
+	SyncFinality,
+	Network,
+	Api,
+	Maintenance,
+}
+
+impl TaskKind {
+	/// Tracing target of the task, which is the module path of its instrumentation
+	fn target(&self) -> &'static str {
+		match self {
+			TaskKind::LightClient => "avail_light::light_client",
+			TaskKind::AppClient => "avail_light::app_client",
+			TaskKind::FatClient => "avail_light::fat_client",
+			TaskKind::SyncClient => "avail_light::sync_client",
+			TaskKind::SyncFinality => "avail_light::sync_finality",
+			TaskKind::Network => "avail_light::network",
+			TaskKind::Api => "avail_light::api",
+			TaskKind::Maintenance => "avail_light::maintenance",
+		}
+	}
+}
+
+impl Display for TaskKind {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.write_str(self.target())
+	}
+}
+
+type ReloadFilter = dyn Fn(EnvFilter) -> Result<()> + Send + Sync;
+
+/// Handle for adjusting log levels of the light client tasks at runtime
+#[derive(Clone)]
+pub struct LogLevels {
+	default: Level,
+	levels: Arc<Mutex<HashMap<TaskKind, LevelFilter>>>,
+	reload: Arc<ReloadFilter>,
+}
+
+impl LogLevels {
+	/// Creates log levels handle, with the function which replaces active subscriber filter
+	pub fn new(
+		default: Level,
+		reload: impl Fn(EnvFilter) -> Result<()> + Send + Sync + 'static,
+	) -> Self {
+		LogLevels {
+			default,
+			levels: Default::default(),
+			reload: Arc::new(reload),
+		}
+	}
+
+	/// Returns filter with the default log level and task specific overrides
+	pub fn filter(&self) -> EnvFilter {
+		let levels = self.levels.lock().unwrap();
+		EnvFilter::new(directives(self.default, &levels).join(","))
+	}
+
+	/// Sets log level of the given task and reloads the active filter
+	pub fn set_task_log_level(&self, task: TaskKind, level: LevelFilter) -> Result<()> {
+		self.levels.lock().unwrap().insert(task, level);
+		(self.reload)(self.filter()).map_err(|error| eyre!("Cannot reload log filter: {error}"))?;
+		info!("Log level of {task} set to {level}");
+		Ok(())
+	}
+}
+
+fn directives(default: Level, levels: &HashMap<TaskKind, LevelFilter>) -> Vec<String> {
+	let mut directives = vec![format!("avail_light={default}")];
+	directives.extend(
+		levels
+			.iter()
+			.map(|(task, level)| format!("{}={level}", task.target())),
+	);
+	directives
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{directives, TaskKind};
+	use std::collections::HashMap;
+	use tracing::{level_filters::LevelFilter, Level};
+
+	#[test]
+	fn task_directives() {
+		let levels = HashMap::from([(TaskKind::Network, LevelFilter::DEBUG)]);
+		assert_eq!(
+			directives(Level::INFO, &levels),
+			vec!["avail_light=INFO", "avail_light::network=debug"]
+		);
+	}
+}
@@ -1,15 +1,21 @@
 use color_eyre::{eyre::WrapErr, Result};
-use std::sync::Arc;
+use std::{
+	sync::Arc,
+	time::{Duration, Instant},
+};
 use tokio::sync::broadcast;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use crate::{
-	network::p2p::Client as P2pClient,
+	network::{p2p::Client as P2pClient, rpc::Event},
 	shutdown::Controller,
 	telemetry::{MetricValue, Metrics},
-	types::BlockVerified,
+	types::{BlockVerified, KademliaMode},
 };
 
+/// Duration after which missing server peers are reported, while there are connected peers
+const NO_SERVER_PEERS_TIMEOUT: Duration = Duration::from_secs(60);
+
 #[derive(Clone, Copy)]
 pub struct StaticConfigParams {
 	pub block_confidence_treshold: f64,
@@ -18,6 +24,64 @@ pub struct StaticConfigParams {
 	pub pruning_interval: u32,
 }
 
+/// Tracks whether any of the connected peers is in Kademlia server mode, which is required for DHT queries
+#[derive(Default)]
+struct ServerPeers {
+	// time since there are connected peers, but none of them is in server mode
+	missing_since: Option<Instant>,
+	reported: bool,
+}
+
+impl ServerPeers {
+	/// Returns the event if server peers are missing for longer than the timeout,
+	/// or if they are connected again after being reported missing
+	fn update(&mut self, connected: usize, servers: usize, now: Instant) -> Option<Event> {
+		if servers > 0 {
+			self.missing_since = None;
+			return std::mem::take(&mut self.reported)
+				.then_some(Event::ServerPeersRecovered { produced_at: now });
+		}
+		// Having no peers at all is not reported
+		if connected == 0 {
+			self.missing_since = None;
+			return None;
+		}
+
+		let missing_since = *self.missing_since.get_or_insert(now);
+		if self.reported || now.duration_since(missing_since) < NO_SERVER_PEERS_TIMEOUT {
+			return None;
+		}
+		self.reported = true;
+		Some(Event::NoServerPeers {
+			connected,
+			produced_at: now,
+		})
+	}
+}
+
+async fn check_server_peers(
+	p2p_client: &P2pClient,
+	server_peers: &mut ServerPeers,
+	event_sender: &broadcast::Sender<Event>,
+) -> Result<()> {
+	let counts = p2p_client.count_peers_by_kademlia_mode().await?;
+	let connected = counts.values().sum();
+	let servers = counts.get(&KademliaMode::Server).copied().unwrap_or(0);
+
+	let Some(event) = server_peers.update(connected, servers, Instant::now()) else {
+		return Ok(());
+	};
+	match event {
+		Event::NoServerPeers { .. } => {
+			warn!(connected, "None of the connected peers is a DHT server")
+		},
+		_ => info!(servers, "DHT server peers are connected again"),
+	}
+	// Sending fails only if there are no subscribers
+	_ = event_sender.send(event);
+	Ok(())
+}
+
 pub async fn process_block(
 	block_number: u32,
 	p2p_client: &P2pClient,
@@ -87,10 +151,12 @@ pub async fn run(
 	metrics: Arc<impl Metrics>,
 	mut block_receiver: broadcast::Receiver<BlockVerified>,
 	static_config_params: StaticConfigParams,
+	event_sender: broadcast::Sender<Event>,
 	shutdown: Controller<String>,
 ) {
 	info!("Starting maintenance...");
 
+	let mut server_peers = ServerPeers::default();
 	loop {
 		let result = match block_receiver.recv().await {
 			Ok(block) => {
@@ -98,10 +164,46 @@ pub async fn run(
 			},
 			Err(error) => Err(error.into()),
 		};
-
 		if let Err(error) = result {
 			let _ = shutdown.trigger_shutdown(format!("{error:#}"));
 			break;
 		}
+
+		// Server peers check is diagnostic only, so its failure doesn't stop the maintenance
+		if let Err(error) = check_server_peers(&p2p_client, &mut server_peers, &event_sender).await
+		{
+			error!("Cannot check DHT server peers: {error:#}");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{ServerPeers, NO_SERVER_PEERS_TIMEOUT};
+	use crate::network::rpc::Event;
+	use std::time::{Duration, Instant};
+
+	#[test]
+	fn server_peers_missing_and_recovered() {
+		let mut server_peers = ServerPeers::default();
+		let now = Instant::now();
+		assert!(server_peers.update(0, 0, now).is_none());
+		assert!(server_peers.update(5, 0, now).is_none());
+
+		let later = now + NO_SERVER_PEERS_TIMEOUT;
+		assert!(matches!(
+			server_peers.update(5, 0, later),
+			Some(Event::NoServerPeers { connected: 5, .. })
+		));
+		// Reported only once
+		assert!(server_peers
+			.update(5, 0, later + Duration::from_secs(1))
+			.is_none());
+
+		assert!(matches!(
+			server_peers.update(5, 1, later),
+			Some(Event::ServerPeersRecovered { .. })
+		));
+		assert!(server_peers.update(5, 1, later).is_none());
 	}
 }
@@ -1,32 +1,44 @@
-use allow_block_list::BlockedPeers;
+use allow_block_list::{AllowedPeers, BlockedPeers};
 use color_eyre::{eyre::WrapErr, Report, Result};
 use libp2p::{
-	autonat, dcutr, identify, identity,
+	autonat,
+	core::{transport::ListenerId, upgrade::Version, Transport},
+	dcutr, identify, identity,
 	kad::{self, PeerRecord, QueryId},
 	mdns, noise, ping, relay,
-	swarm::NetworkBehaviour,
-	tcp, upnp, yamux, PeerId, Swarm, SwarmBuilder,
+	swarm::{behaviour::toggle::Toggle, NetworkBehaviour},
+	tcp, upnp, yamux, Multiaddr, PeerId, Swarm, SwarmBuilder,
 };
 use multihash::{self, Hasher};
-use std::collections::HashMap;
+use std::{
+	collections::{HashMap, VecDeque},
+	fmt::{self, Display, Formatter},
+	sync::{atomic::AtomicUsize, Arc},
+	time::{Duration, Instant},
+};
 use tokio::sync::{
 	mpsc::{self},
 	oneshot,
 };
-use tracing::info;
+use tracing::{info, trace};
 
 #[cfg(feature = "network-analysis")]
 pub mod analyzer;
 mod client;
 mod event_loop;
 mod kad_mem_store;
+mod socks5;
 
-use crate::types::{LibP2PConfig, SecretKey};
+use crate::{
+	shutdown::Controller,
+	types::{KademliaMode, LibP2PConfig, SecretKey},
+};
 pub use client::Client;
 pub use event_loop::EventLoop;
+use event_loop::PeersState;
 pub use kad_mem_store::MemoryStoreConfig;
 
-use self::{client::BlockStat, kad_mem_store::MemoryStore};
+use self::{client::BlockStat, kad_mem_store::MemoryStore, socks5::Socks5Transport};
 use libp2p_allow_block_list as allow_block_list;
 
 #[derive(Debug)]
@@ -36,12 +48,55 @@ pub enum QueryChannel {
 	Bootstrap(oneshot::Sender<Result<()>>),
 }
 
+/// Requested listen address, with the outcome of binding to it
+pub type ListenResult = (Multiaddr, Result<(), String>);
+/// Requested listen address, with addresses it resolved to, or the reason listening failed
+pub type ListenerStatus = (Multiaddr, Result<Vec<Multiaddr>, String>);
+
+/// Listener on the requested address, with addresses it resolved to, or the reason it failed
+#[derive(Debug)]
+pub struct Listener {
+	id: Option<ListenerId>,
+	address: Multiaddr,
+	result: Result<Vec<Multiaddr>, String>,
+}
+
+/// Details of the connected peer, gathered from the connection, identify and ping events
+#[derive(Clone, Debug)]
+pub struct PeerInfo {
+	/// Remote addresses of the established connections
+	pub addresses: Vec<Multiaddr>,
+	/// Agent version advertised through Identify
+	pub agent_version: Option<String>,
+	/// Kademlia mode advertised through Identify, if the peer runs a compatible protocol
+	pub kademlia_mode: Option<KademliaMode>,
+	/// Round trip time of the latest successful ping
+	pub rtt: Option<Duration>,
+	/// Time when the peer was connected, kept while any of the connections is established
+	pub connected_since: Instant,
+}
+
+impl PeerInfo {
+	fn new() -> Self {
+		PeerInfo {
+			addresses: vec![],
+			agent_version: None,
+			kademlia_mode: None,
+			rtt: None,
+			connected_since: Instant::now(),
+		}
+	}
+}
+
 pub struct EventLoopEntries<'a> {
 	swarm: &'a mut Swarm<Behaviour>,
 	pending_kad_queries: &'a mut HashMap<QueryId, QueryChannel>,
 	pending_swarm_events: &'a mut HashMap<PeerId, oneshot::Sender<Result<()>>>,
 	/// <block_num, (total_cells, result_cell_counter, time_stat)>
 	active_blocks: &'a mut HashMap<u32, BlockStat>,
+	listeners: &'a mut Vec<Listener>,
+	/// Connected peers and connection statistics, read-only
+	peers: &'a PeersState,
 }
 
 impl<'a> EventLoopEntries<'a> {
@@ -50,12 +105,16 @@ impl<'a> EventLoopEntries<'a> {
 		pending_kad_queries: &'a mut HashMap<QueryId, QueryChannel>,
 		pending_swarm_events: &'a mut HashMap<PeerId, oneshot::Sender<Result<()>>>,
 		active_blocks: &'a mut HashMap<u32, BlockStat>,
+		listeners: &'a mut Vec<Listener>,
+		peers: &'a PeersState,
 	) -> Self {
 		Self {
 			swarm,
 			pending_kad_queries,
 			pending_swarm_events,
 			active_blocks,
+			listeners,
+			peers,
 		}
 	}
 
@@ -78,13 +137,66 @@ impl<'a> EventLoopEntries<'a> {
 	pub fn swarm(&mut self) -> &mut Swarm<Behaviour> {
 		self.swarm
 	}
+
+	pub fn protocol_mismatches(&self) -> &VecDeque<(PeerId, String)> {
+		&self.peers.protocol_mismatches
+	}
+
+	pub fn connection_history(&self) -> &VecDeque<(Instant, usize)> {
+		&self.peers.connection_history
+	}
+
+	pub fn connected_peers(&self) -> &HashMap<PeerId, PeerInfo> {
+		&self.peers.connected
+	}
+
+	pub fn out_peers(&self) -> Option<u32> {
+		self.peers.out_peers
+	}
 }
 
 pub trait Command {
 	fn run(&mut self, entries: EventLoopEntries) -> Result<(), Report>;
 	fn abort(&mut self, error: Report);
+	/// Returns true if the caller is no longer waiting for the result,
+	/// so the command without side effects can be skipped.
+	fn is_canceled(&self) -> bool {
+		false
+	}
+}
+
+fn is_closed<T>(sender: &Option<oneshot::Sender<T>>) -> bool {
+	sender.as_ref().map_or(true, oneshot::Sender::is_closed)
+}
+
+/// Sends the command result back to the caller, if the caller is still waiting for it.
+fn send_response<T>(sender: &mut Option<oneshot::Sender<Result<T>>>, result: Result<T>) {
+	let Some(sender) = sender.take() else {
+		trace!("Command response is already sent");
+		return;
+	};
+	if sender.send(result).is_err() {
+		trace!("Command receiver dropped, discarding the response");
+	}
+}
+
+/// Errors returned by the P2P [Client], which can be matched using [`color_eyre::Report::downcast_ref`].
+#[derive(Debug, PartialEq)]
+pub enum ClientError {
+	/// Event loop is not running anymore, so commands cannot be executed
+	Shutdown,
+}
+
+impl Display for ClientError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			ClientError::Shutdown => write!(f, "P2P event loop is shut down"),
+		}
+	}
 }
 
+impl std::error::Error for ClientError {}
+
 type SendableCommand = Box<dyn Command + Send + Sync>;
 type CommandSender = mpsc::UnboundedSender<SendableCommand>;
 type CommandReceiver = mpsc::UnboundedReceiver<SendableCommand>;
@@ -96,12 +208,47 @@ pub struct Behaviour {
 	kademlia: kad::Behaviour<MemoryStore>,
 	identify: identify::Behaviour,
 	ping: ping::Behaviour,
-	mdns: mdns::tokio::Behaviour,
+	mdns: Toggle<mdns::tokio::Behaviour>,
 	auto_nat: autonat::Behaviour,
 	relay_client: relay::client::Behaviour,
 	dcutr: dcutr::Behaviour,
 	upnp: upnp::tokio::Behaviour,
+	// peers blocked for running an incompatible protocol
 	blocked_peers: allow_block_list::Behaviour<BlockedPeers>,
+	// peers forgotten on request, kept separately so clearing them doesn't unblock the incompatible ones
+	denied_peers: allow_block_list::Behaviour<BlockedPeers>,
+	allowed_peers: Toggle<allow_block_list::Behaviour<AllowedPeers>>,
+}
+
+/// Builds the P2P event loop and the client for sending commands to it.
+/// Network is not started until [`EventLoop::run`] is spawned.
+pub async fn init(
+	cfg: LibP2PConfig,
+	id_keys: &identity::Keypair,
+	dht_parallelization_limit: usize,
+	ttl: u64,
+	is_fat_client: bool,
+	is_ws_transport: bool,
+	shutdown: Controller<String>,
+) -> Result<(Client, EventLoop)> {
+	// create sender channel for P2P event loop commands
+	let (command_sender, command_receiver) = mpsc::unbounded_channel();
+	let connections = Arc::new(AtomicUsize::new(0));
+
+	let event_loop = EventLoop::new(
+		cfg,
+		id_keys,
+		is_fat_client,
+		is_ws_transport,
+		command_receiver,
+		connections.clone(),
+		shutdown,
+	)
+	.await
+	.wrap_err("Unable to build P2P event loop")?;
+	let client = Client::new(command_sender, connections, dht_parallelization_limit, ttl);
+
+	Ok((client, event_loop))
 }
 
 fn generate_config(config: libp2p::swarm::Config, cfg: &LibP2PConfig) -> libp2p::swarm::Config {
@@ -148,9 +295,29 @@ async fn build_swarm(
 			dcutr: dcutr::Behaviour::new(key.public().to_peer_id()),
 			kademlia: kad::Behaviour::with_config(key.public().to_peer_id(), kad_store, cfg.into()),
 			auto_nat: autonat::Behaviour::new(key.public().to_peer_id(), autonat_cfg),
-			mdns: mdns::Behaviour::new(mdns::Config::default(), key.public().to_peer_id())?,
+			// Local discovery would reveal the node, so it is disabled behind the proxy
+			mdns: match cfg.socks5_proxy {
+				Some(_) => None,
+				None => Some(mdns::Behaviour::new(
+					mdns::Config::default(),
+					key.public().to_peer_id(),
+				)?),
+			}
+			.into(),
 			upnp: upnp::tokio::Behaviour::default(),
 			blocked_peers: allow_block_list::Behaviour::default(),
+			denied_peers: allow_block_list::Behaviour::default(),
+			allowed_peers: cfg
+				.peer_allowlist
+				.as_ref()
+				.map(|allowlist| {
+					let mut allowed_peers = allow_block_list::Behaviour::default();
+					for peer_id in allowlist {
+						allowed_peers.allow_peer(*peer_id);
+					}
+					allowed_peers
+				})
+				.into(),
 		})
 	};
 
@@ -162,13 +329,36 @@ async fn build_swarm(
 			.with_behaviour(behaviour)?
 			.with_swarm_config(|c| generate_config(c, cfg))
 			.build();
+	} else if let Some(proxy) = cfg.socks5_proxy {
+		// Proxy resolves the domain names, so DNS transport is not used
+		swarm = tokio_swarm
+			.with_other_transport(|key| {
+				Ok::<_, noise::Error>(
+					Socks5Transport::new(proxy)
+						.upgrade(Version::V1)
+						.authenticate(noise::Config::new(key)?)
+						.multiplex(yamux::Config::default())
+						.outbound_timeout(cfg.dial_timeout),
+				)
+			})?
+			.with_relay_client(noise::Config::new, yamux::Config::default)?
+			.with_behaviour(behaviour)?
+			.with_swarm_config(|c| generate_config(c, cfg))
+			.build();
 	} else {
+		// TCP transport is built as the other transport, to abort the dials which don't complete in time
 		swarm = tokio_swarm
-			.with_tcp(
-				tcp::Config::default().port_reuse(false).nodelay(false),
-				noise::Config::new,
-				yamux::Config::default,
-			)?
+			.with_quic()
+			.with_other_transport(|key| {
+				let tcp_config = tcp::Config::default().port_reuse(false).nodelay(false);
+				Ok::<_, noise::Error>(
+					tcp::tokio::Transport::new(tcp_config)
+						.upgrade(Version::V1Lazy)
+						.authenticate(noise::Config::new(key)?)
+						.multiplex(yamux::Config::default())
+						.outbound_timeout(cfg.dial_timeout),
+				)
+			})?
 			.with_dns()?
 			.with_relay_client(noise::Config::new, yamux::Config::default)?
 			.with_behaviour(behaviour)?
@@ -215,3 +405,32 @@ pub fn keypair(cfg: &LibP2PConfig) -> Result<(libp2p::identity::Keypair, String)
 	let peer_id = PeerId::from(keypair.public()).to_string();
 	Ok((keypair, peer_id))
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{send_response, Client, ClientError};
+	use color_eyre::Result;
+	use tokio::sync::{mpsc, oneshot};
+
+	#[tokio::test]
+	async fn client_returns_shutdown_error() {
+		let (command_sender, command_receiver) = mpsc::unbounded_channel();
+		drop(command_receiver);
+		let client = Client::new(command_sender, Default::default(), 20, 3600);
+
+		let error = client.bootstrap().await.unwrap_err();
+		assert_eq!(error.downcast_ref(), Some(&ClientError::Shutdown));
+	}
+
+	#[test]
+	fn send_response_to_dropped_receiver() {
+		let (sender, receiver) = oneshot::channel::<Result<()>>();
+		drop(receiver);
+		let mut sender = Some(sender);
+
+		send_response(&mut sender, Ok(()));
+		assert!(sender.is_none());
+		// Sending again is a no-op
+		send_response(&mut sender, Ok(()));
+	}
+}
@@ -1,4 +1,7 @@
-use super::{Command, CommandSender, EventLoopEntries, QueryChannel, SendableCommand};
+use super::{
+	is_closed, send_response, ClientError, Command, CommandSender, EventLoopEntries, ListenResult,
+	Listener, ListenerStatus, PeerInfo, QueryChannel, SendableCommand,
+};
 use color_eyre::{
 	eyre::{eyre, WrapErr},
 	Report, Result,
@@ -10,21 +13,29 @@ use kate_recovery::{
 	matrix::{Dimensions, Position, RowIndex},
 };
 use libp2p::{
-	kad::{PeerRecord, Quorum, Record, RecordKey},
-	swarm::dial_opts::DialOpts,
+	kad::{NodeStatus, PeerRecord, Quorum, Record, RecordKey},
+	swarm::dial_opts::{DialOpts, PeerCondition},
 	Multiaddr, PeerId,
 };
 use std::str;
 use std::{
 	collections::HashMap,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
 	time::{Duration, Instant},
 };
 use tokio::sync::oneshot;
 use tracing::{debug, trace};
 
+use crate::types::KademliaMode;
+
 #[derive(Clone)]
 pub struct Client {
 	command_sender: CommandSender,
+	/// Number of established connections, updated by the event loop
+	connections: Arc<AtomicUsize>,
 	/// Number of cells to fetch in parallel
 	dht_parallelization_limit: usize,
 	/// Cell time to live in DHT (in seconds)
@@ -93,11 +104,7 @@ impl Command for PruneExpiredRecords {
 		store.retain(|_, record| !record.is_expired(self.now));
 		let after = store.records_iter().count();
 
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Ok(before - after))
-			.expect("PruneExpiredRecords receiver dropped");
+		send_response(&mut self.response_sender, Ok(before - after));
 
 		Ok(())
 	}
@@ -106,31 +113,59 @@ impl Command for PruneExpiredRecords {
 }
 
 struct StartListening {
-	addr: Multiaddr,
-	response_sender: Option<oneshot::Sender<Result<()>>>,
+	addrs: Vec<Multiaddr>,
+	response_sender: Option<oneshot::Sender<Result<Vec<ListenResult>>>>,
 }
 
 impl Command for StartListening {
 	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
-		_ = entries.swarm().listen_on(self.addr.clone())?;
+		let mut results = vec![];
+		for address in self.addrs.drain(..) {
+			let (id, result) = match entries.swarm().listen_on(address.clone()) {
+				Ok(id) => (Some(id), Ok(vec![])),
+				Err(error) => (None, Err(error.to_string())),
+			};
+			results.push((address.clone(), result.clone().map(|_| ())));
+			entries.listeners.push(Listener {
+				id,
+				address,
+				result,
+			});
+		}
 
 		// send result back
-		// TODO: consider what to do if this results with None
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Ok(()))
-			.expect("StartListening receiver dropped");
+		send_response(&mut self.response_sender, Ok(results));
 		Ok(())
 	}
 
 	fn abort(&mut self, error: Report) {
-		// TODO: consider what to do if this results with None
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Err(error))
-			.expect("StartListening receiver dropped");
+		send_response(&mut self.response_sender, Err(error));
+	}
+}
+
+struct ListListeners {
+	response_sender: Option<oneshot::Sender<Result<Vec<ListenerStatus>>>>,
+}
+
+impl Command for ListListeners {
+	fn run(&mut self, entries: EventLoopEntries) -> Result<()> {
+		let listeners = entries
+			.listeners
+			.iter()
+			.map(|listener| (listener.address.clone(), listener.result.clone()))
+			.collect::<Vec<_>>();
+
+		// send result back
+		send_response(&mut self.response_sender, Ok(listeners));
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		send_response(&mut self.response_sender, Err(error));
+	}
+
+	fn is_canceled(&self) -> bool {
+		is_closed(&self.response_sender)
 	}
 }
 
@@ -161,18 +196,156 @@ impl Command for Bootstrap {
 		let query_id = entries.behavior_mut().kademlia.bootstrap()?;
 
 		// insert response channel into KAD Queries pending map
-		let response_sender = self.response_sender.take().unwrap();
+		let response_sender = self
+			.response_sender
+			.take()
+			.ok_or_else(|| eyre!("Response sender is already taken"))?;
 		entries.insert_query(query_id, super::QueryChannel::Bootstrap(response_sender));
 		Ok(())
 	}
 
 	fn abort(&mut self, error: Report) {
-		// TODO: consider what to do if this results with None
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Err(error))
-			.expect("Bootstrap receiver dropped");
+		send_response(&mut self.response_sender, Err(error));
+	}
+}
+
+struct TriggerDiscovery;
+
+impl Command for TriggerDiscovery {
+	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
+		// Kademlia bootstrap performs random walk, discovering new peers
+		let query_id = entries.behavior_mut().kademlia.bootstrap()?;
+		debug!("Discovery triggered, query: {query_id:?}");
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		debug!("Discovery cannot be triggered: {error}");
+	}
+}
+
+struct ReconnectAll {
+	bootstraps: Vec<(PeerId, Multiaddr)>,
+}
+
+impl Command for ReconnectAll {
+	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
+		let out_peers = entries.out_peers().unwrap_or(0) as usize;
+		let swarm = entries.swarm();
+		let connected = swarm.connected_peers().copied().collect::<Vec<_>>();
+		for peer_id in &connected {
+			// Closing the connection emits the regular connection closed event
+			_ = swarm.disconnect_peer_id(*peer_id);
+		}
+
+		// Peers whose connections are still closing are skipped, bootstrap query reaches them later
+		let routing_table_peers = swarm
+			.behaviour_mut()
+			.kademlia
+			.kbuckets()
+			.flat_map(|bucket| {
+				bucket
+					.iter()
+					.map(|entry| {
+						let peer_id = *entry.node.key.preimage();
+						(peer_id, entry.node.value.clone().into_vec())
+					})
+					.collect::<Vec<_>>()
+			})
+			.filter(|(peer_id, _)| !connected.contains(peer_id))
+			.take(out_peers)
+			.collect::<Vec<_>>();
+		let bootstraps = self
+			.bootstraps
+			.drain(..)
+			.map(|(peer_id, address)| (peer_id, vec![address]));
+		for (peer_id, addresses) in bootstraps.chain(routing_table_peers) {
+			let opts = DialOpts::peer_id(peer_id)
+				.addresses(addresses)
+				.condition(PeerCondition::DisconnectedAndNotDialing)
+				.build();
+			if let Err(error) = swarm.dial(opts) {
+				debug!("Cannot redial peer {peer_id}: {error}");
+			}
+		}
+
+		let query_id = swarm.behaviour_mut().kademlia.bootstrap()?;
+		debug!(
+			"Disconnected from {} peers, reconnecting, bootstrap query: {query_id:?}",
+			connected.len()
+		);
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		debug!("Connections cannot be reconnected: {error}");
+	}
+}
+
+struct ForgetPeer {
+	peer_id: PeerId,
+}
+
+impl Command for ForgetPeer {
+	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
+		let behaviour = entries.behavior_mut();
+		behaviour.kademlia.remove_peer(&self.peer_id);
+		// Blocking closes established connections and denies new ones, including dials
+		behaviour.denied_peers.block_peer(self.peer_id);
+		debug!(
+			"Peer {} is forgotten and added to the deny list",
+			self.peer_id
+		);
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		debug!("Peer {} cannot be forgotten: {error}", self.peer_id);
+	}
+}
+
+struct GetDenyList {
+	response_sender: Option<oneshot::Sender<Result<Vec<PeerId>>>>,
+}
+
+impl Command for GetDenyList {
+	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
+		let deny_list = entries
+			.behavior_mut()
+			.denied_peers
+			.blocked_peers()
+			.iter()
+			.copied()
+			.collect();
+
+		send_response(&mut self.response_sender, Ok(deny_list));
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		send_response(&mut self.response_sender, Err(error));
+	}
+
+	fn is_canceled(&self) -> bool {
+		is_closed(&self.response_sender)
+	}
+}
+
+struct ClearDenyList;
+
+impl Command for ClearDenyList {
+	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
+		let denied_peers = &mut entries.behavior_mut().denied_peers;
+		let denied = denied_peers.blocked_peers().clone();
+		for peer_id in &denied {
+			denied_peers.unblock_peer(*peer_id);
+		}
+		debug!("Deny list cleared, {} peers removed", denied.len());
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		debug!("Deny list cannot be cleared: {error}");
 	}
 }
 
@@ -186,18 +359,20 @@ impl Command for GetKadRecord {
 		let query_id = entries.behavior_mut().kademlia.get_record(self.key.clone());
 
 		// insert response channel into KAD Queries pending map
-		let response_sender = self.response_sender.take().unwrap();
+		let response_sender = self
+			.response_sender
+			.take()
+			.ok_or_else(|| eyre!("Response sender is already taken"))?;
 		entries.insert_query(query_id, super::QueryChannel::GetRecord(response_sender));
 		Ok(())
 	}
 
 	fn abort(&mut self, error: Report) {
-		// TODO: consider what to do if this results with None
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Err(error))
-			.expect("GetKadRecord receiver dropped");
+		send_response(&mut self.response_sender, Err(error));
+	}
+
+	fn is_canceled(&self) -> bool {
+		is_closed(&self.response_sender)
 	}
 }
 
@@ -229,7 +404,7 @@ impl Command for PutKadRecord {
 				.behavior_mut()
 				.kademlia
 				.put_record(record, self.quorum)
-				.expect("Unable to perform Kademlia PUT operation.");
+				.wrap_err("Unable to perform Kademlia PUT operation")?;
 			entries.insert_query(query_id, QueryChannel::PutRecord);
 		}
 		Ok(())
@@ -245,22 +420,19 @@ struct CountConnectedPeers {
 impl Command for CountConnectedPeers {
 	fn run(&mut self, entries: EventLoopEntries) -> Result<()> {
 		// send result back
-		// TODO: consider what to do if this results with None
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Ok(entries.swarm.network_info().num_peers()))
-			.expect("CountDHTPeers receiver dropped");
+		send_response(
+			&mut self.response_sender,
+			Ok(entries.swarm.network_info().num_peers()),
+		);
 		Ok(())
 	}
 
 	fn abort(&mut self, error: Report) {
-		// TODO: consider what to do if this results with None
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Err(error))
-			.expect("CountDHTPeers receiver dropped");
+		send_response(&mut self.response_sender, Err(error));
+	}
+
+	fn is_canceled(&self) -> bool {
+		is_closed(&self.response_sender)
 	}
 }
 
@@ -277,22 +449,158 @@ impl Command for ListConnectedPeers {
 			.collect::<Vec<_>>();
 
 		// send result back
-		// TODO: consider what to do if this results with None
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Ok(connected_peer_list))
-			.expect("CountDHTPeers receiver dropped");
+		send_response(&mut self.response_sender, Ok(connected_peer_list));
 		Ok(())
 	}
 
 	fn abort(&mut self, error: Report) {
-		// TODO: consider what to do if this results with None
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Err(error))
-			.expect("CountDHTPeers receiver dropped");
+		send_response(&mut self.response_sender, Err(error));
+	}
+
+	fn is_canceled(&self) -> bool {
+		is_closed(&self.response_sender)
+	}
+}
+
+struct CountPeersByKademliaMode {
+	response_sender: Option<oneshot::Sender<Result<HashMap<KademliaMode, usize>>>>,
+}
+
+impl Command for CountPeersByKademliaMode {
+	fn run(&mut self, entries: EventLoopEntries) -> Result<()> {
+		let mut counts = HashMap::new();
+		for mode in entries
+			.connected_peers()
+			.values()
+			.filter_map(|peer| peer.kademlia_mode)
+		{
+			*counts.entry(mode).or_insert(0) += 1;
+		}
+
+		send_response(&mut self.response_sender, Ok(counts));
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		send_response(&mut self.response_sender, Err(error));
+	}
+
+	fn is_canceled(&self) -> bool {
+		is_closed(&self.response_sender)
+	}
+}
+
+struct ListProtocolMismatches {
+	response_sender: Option<oneshot::Sender<Result<Vec<(PeerId, String)>>>>,
+}
+
+impl Command for ListProtocolMismatches {
+	fn run(&mut self, entries: EventLoopEntries) -> Result<()> {
+		// Peers unblocked since the mismatch was detected are not listed
+		let blocked_peers = entries.behavior_mut().blocked_peers.blocked_peers().clone();
+		let mismatches = entries
+			.protocol_mismatches()
+			.iter()
+			.filter(|(peer_id, _)| blocked_peers.contains(peer_id))
+			.cloned()
+			.collect();
+
+		send_response(&mut self.response_sender, Ok(mismatches));
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		send_response(&mut self.response_sender, Err(error));
+	}
+
+	fn is_canceled(&self) -> bool {
+		is_closed(&self.response_sender)
+	}
+}
+
+struct GetConnectionHistory {
+	response_sender: Option<oneshot::Sender<Result<Vec<(Instant, usize)>>>>,
+}
+
+impl Command for GetConnectionHistory {
+	fn run(&mut self, entries: EventLoopEntries) -> Result<()> {
+		let history = entries.connection_history().iter().copied().collect();
+
+		send_response(&mut self.response_sender, Ok(history));
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		send_response(&mut self.response_sender, Err(error));
+	}
+
+	fn is_canceled(&self) -> bool {
+		is_closed(&self.response_sender)
+	}
+}
+
+struct GetPeerInfo {
+	peer_id: PeerId,
+	response_sender: Option<oneshot::Sender<Result<Option<PeerInfo>>>>,
+}
+
+impl Command for GetPeerInfo {
+	fn run(&mut self, entries: EventLoopEntries) -> Result<()> {
+		let peer_info = entries.connected_peers().get(&self.peer_id).cloned();
+
+		send_response(&mut self.response_sender, Ok(peer_info));
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		send_response(&mut self.response_sender, Err(error));
+	}
+
+	fn is_canceled(&self) -> bool {
+		is_closed(&self.response_sender)
+	}
+}
+
+struct GetKnownPeers {
+	limit: usize,
+	response_sender: Option<oneshot::Sender<Result<Vec<(PeerId, Vec<Multiaddr>)>>>>,
+}
+
+impl Command for GetKnownPeers {
+	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
+		// Routing table contains peers we have connected to, currently connected ones are listed first
+		let mut known_peers = entries
+			.behavior_mut()
+			.kademlia
+			.kbuckets()
+			.flat_map(|bucket| {
+				bucket
+					.iter()
+					.map(|entry| {
+						let is_connected = entry.status == NodeStatus::Connected;
+						let peer_id = *entry.node.key.preimage();
+						(is_connected, peer_id, entry.node.value.clone().into_vec())
+					})
+					.collect::<Vec<_>>()
+			})
+			.collect::<Vec<_>>();
+		known_peers.sort_by_key(|(is_connected, ..)| !is_connected);
+
+		let known_peers = known_peers
+			.into_iter()
+			.take(self.limit)
+			.map(|(_, peer_id, addresses)| (peer_id, addresses))
+			.collect();
+		send_response(&mut self.response_sender, Ok(known_peers));
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		send_response(&mut self.response_sender, Err(error));
+	}
+
+	fn is_canceled(&self) -> bool {
+		is_closed(&self.response_sender)
 	}
 }
 
@@ -307,10 +615,10 @@ impl Command for GetCellsInDHTPerBlock {
 			let vec_key = record.0.to_vec();
 			let record_key = str::from_utf8(&vec_key);
 
-			let (block_num, _) = record_key
-				.expect("unable to cast key to string")
-				.split_once(':')
-				.expect("unable to split the key string");
+			let Some((block_num, _)) = record_key.ok().and_then(|key| key.split_once(':')) else {
+				trace!("Skipping record with unexpected key format");
+				continue;
+			};
 
 			let count = occurrence_map.entry(block_num.to_string()).or_insert(0);
 			*count += 1;
@@ -325,22 +633,16 @@ impl Command for GetCellsInDHTPerBlock {
 			);
 		}
 		// send result back
-		// TODO: consider what to do if this results with None
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Ok(()))
-			.expect("GetCellsInDHTPerBlock receiver dropped");
+		send_response(&mut self.response_sender, Ok(()));
 		Ok(())
 	}
 
 	fn abort(&mut self, error: Report) {
-		// TODO: consider what to do if this results with None
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Err(error))
-			.expect("GetCellsInDHTPerBlock receiver dropped");
+		send_response(&mut self.response_sender, Err(error));
+	}
+
+	fn is_canceled(&self) -> bool {
+		is_closed(&self.response_sender)
 	}
 }
 
@@ -357,22 +659,16 @@ impl Command for GetMultiaddress {
 			.collect::<Vec<_>>();
 
 		// send result back
-		// TODO: consider what to do if this results with None
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Ok(last_address))
-			.expect("GetMultiaddress receiver dropped");
+		send_response(&mut self.response_sender, Ok(last_address));
 		Ok(())
 	}
 
 	fn abort(&mut self, error: Report) {
-		// TODO: consider what to do if this results with None
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Err(error))
-			.expect("GetMultiaddress receiver dropped");
+		send_response(&mut self.response_sender, Err(error));
+	}
+
+	fn is_canceled(&self) -> bool {
+		is_closed(&self.response_sender)
 	}
 }
 
@@ -385,12 +681,7 @@ impl Command for ReduceKademliaMapSize {
 		entries.behavior_mut().kademlia.store_mut().shrink_hashmap();
 
 		// send result back
-		// TODO: consider what to do if this results with None
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Ok(()))
-			.expect("ReduceKademliaMapSize receiver dropped");
+		send_response(&mut self.response_sender, Ok(()));
 		Ok(())
 	}
 
@@ -413,11 +704,7 @@ impl Command for GetKademliaMapSize {
 			.records_iter()
 			.count();
 
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Ok(size))
-			.expect("GetKademliaMapSize receiver dropped");
+		send_response(&mut self.response_sender, Ok(size));
 		Ok(())
 	}
 
@@ -425,6 +712,10 @@ impl Command for GetKademliaMapSize {
 		// theres should be no errors from running this Command
 		debug!("No possible errors for GetKademliaMapSize");
 	}
+
+	fn is_canceled(&self) -> bool {
+		is_closed(&self.response_sender)
+	}
 }
 
 struct DialPeer {
@@ -442,17 +733,17 @@ impl Command for DialPeer {
 		)?;
 
 		// insert response channel into Swarm Events pending map
-		entries.insert_swarm_event(self.peer_id, self.response_sender.take().unwrap());
+		entries.insert_swarm_event(
+			self.peer_id,
+			self.response_sender
+				.take()
+				.ok_or_else(|| eyre!("Response sender is already taken"))?,
+		);
 		Ok(())
 	}
 
 	fn abort(&mut self, error: Report) {
-		// TODO: consider what to do if this results with None
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Err(error))
-			.expect("DialPeer receiver dropped");
+		send_response(&mut self.response_sender, Err(error));
 	}
 }
 
@@ -470,12 +761,7 @@ impl Command for AddAutonatServer {
 			.add_server(self.peer_id, Some(self.address.clone()));
 
 		// send result back
-		// TODO: consider what to do if this results with None
-		self.response_sender
-			.take()
-			.unwrap()
-			.send(Ok(()))
-			.expect("AddAutonatServer receiver dropped");
+		send_response(&mut self.response_sender, Ok(()));
 		Ok(())
 	}
 
@@ -486,9 +772,15 @@ impl Command for AddAutonatServer {
 }
 
 impl Client {
-	pub fn new(sender: CommandSender, dht_parallelization_limit: usize, ttl: u64) -> Self {
+	pub fn new(
+		sender: CommandSender,
+		connections: Arc<AtomicUsize>,
+		dht_parallelization_limit: usize,
+		ttl: u64,
+	) -> Self {
 		Self {
 			command_sender: sender,
+			connections,
 			dht_parallelization_limit,
 			ttl,
 		}
@@ -502,16 +794,27 @@ impl Client {
 		let command = command_with_sender(response_sender);
 		self.command_sender
 			.send(command)
-			.wrap_err("receiver should not be dropped")?;
+			.map_err(|_| Report::new(ClientError::Shutdown))?;
 		response_receiver
 			.await
-			.wrap_err("sender should not be dropped")?
+			.map_err(|_| Report::new(ClientError::Shutdown))?
 	}
 
-	pub async fn start_listening(&self, addr: Multiaddr) -> Result<()> {
+	/// Starts listening on all given addresses, returning the bind outcome for each of them
+	pub async fn start_listening(&self, addrs: Vec<Multiaddr>) -> Result<Vec<ListenResult>> {
 		self.execute_sync(|response_sender| {
 			Box::new(StartListening {
-				addr,
+				addrs,
+				response_sender: Some(response_sender),
+			})
+		})
+		.await
+	}
+
+	/// Returns requested listen addresses, with addresses they resolved to, or the reason listening failed
+	pub async fn list_listeners(&self) -> Result<Vec<ListenerStatus>> {
+		self.execute_sync(|response_sender| {
+			Box::new(ListListeners {
 				response_sender: Some(response_sender),
 			})
 		})
@@ -521,7 +824,8 @@ impl Client {
 	pub async fn add_address(&self, peer_id: PeerId, peer_addr: Multiaddr) -> Result<()> {
 		self.command_sender
 			.send(Box::new(AddAddress { peer_id, peer_addr }))
-			.context("failed to add address to the routing table")
+			.map_err(|_| Report::new(ClientError::Shutdown))
+			.wrap_err("failed to add address to the routing table")
 	}
 
 	pub async fn dial_peer(&self, peer_id: PeerId, peer_address: Multiaddr) -> Result<()> {
@@ -544,6 +848,48 @@ impl Client {
 		.await
 	}
 
+	/// Immediately starts peer discovery, without waiting for the next periodic bootstrap
+	pub async fn trigger_discovery(&self) -> Result<()> {
+		self.command_sender
+			.send(Box::new(TriggerDiscovery))
+			.map_err(|_| Report::new(ClientError::Shutdown))
+	}
+
+	/// Closes all current connections and dials the bootstrap nodes again, e.g. after network conditions have changed.
+	/// At most `out_peers` peers from the routing table are dialed along with them.
+	pub async fn reconnect_all(&self, bootstraps: Vec<(PeerId, Multiaddr)>) -> Result<()> {
+		self.command_sender
+			.send(Box::new(ReconnectAll { bootstraps }))
+			.map_err(|_| Report::new(ClientError::Shutdown))
+	}
+
+	/// Disconnects the peer and adds it to the deny list, so it is not dialed or accepted again.
+	/// Unlike a regular disconnect, peer is removed from the routing table as well.
+	pub async fn forget_peer(&self, peer_id: PeerId) -> Result<()> {
+		self.command_sender
+			.send(Box::new(ForgetPeer { peer_id }))
+			.map_err(|_| Report::new(ClientError::Shutdown))
+	}
+
+	/// Returns forgotten peers.
+	/// Peers blocked for running an incompatible protocol are not included, see [`Client::list_protocol_mismatches`].
+	pub async fn deny_list(&self) -> Result<Vec<PeerId>> {
+		self.execute_sync(|response_sender| {
+			Box::new(GetDenyList {
+				response_sender: Some(response_sender),
+			})
+		})
+		.await
+	}
+
+	/// Removes all peers from the deny list.
+	/// Peers blocked for running an incompatible protocol stay blocked.
+	pub async fn clear_deny_list(&self) -> Result<()> {
+		self.command_sender
+			.send(Box::new(ClearDenyList))
+			.map_err(|_| Report::new(ClientError::Shutdown))
+	}
+
 	pub async fn add_autonat_server(&self, peer_id: PeerId, address: Multiaddr) -> Result<()> {
 		self.execute_sync(|response_sender| {
 			Box::new(AddAutonatServer {
@@ -589,7 +935,7 @@ impl Client {
 				quorum,
 				block_num,
 			}))
-			.context("receiver should not be dropped")
+			.map_err(|_| Report::new(ClientError::Shutdown))
 	}
 
 	pub async fn count_dht_entries(&self) -> Result<usize> {
@@ -601,6 +947,12 @@ impl Client {
 		.await
 	}
 
+	/// Returns the current number of established connections, read without a round trip to the event loop.
+	/// Unlike [`Client::list_connected_peers`], connections are counted, and multiple connections to the same peer are included.
+	pub fn live_connections(&self) -> usize {
+		self.connections.load(Ordering::Relaxed)
+	}
+
 	pub async fn list_connected_peers(&self) -> Result<Vec<String>> {
 		self.execute_sync(|response_sender| {
 			Box::new(ListConnectedPeers {
@@ -610,6 +962,79 @@ impl Client {
 		.await
 	}
 
+	/// Waits until at least `min` peers are connected, checking the number of connected peers every second.
+	/// Returns the number of connected peers, or an error if the timeout elapses first.
+	pub async fn wait_for_peers(&self, min: usize, timeout: Duration) -> Result<usize> {
+		let wait = async {
+			loop {
+				let connected = self.list_connected_peers().await?.len();
+				if connected >= min {
+					return Ok(connected);
+				}
+				tokio::time::sleep(Duration::from_secs(1)).await;
+			}
+		};
+		tokio::time::timeout(timeout, wait)
+			.await
+			.map_err(|_| eyre!("Timed out waiting for {min} connected peers"))?
+	}
+
+	/// Counts connected peers by Kademlia mode (client or server), advertised through Identify.
+	/// Peers which are not identified yet are not counted.
+	pub async fn count_peers_by_kademlia_mode(&self) -> Result<HashMap<KademliaMode, usize>> {
+		self.execute_sync(|response_sender| {
+			Box::new(CountPeersByKademliaMode {
+				response_sender: Some(response_sender),
+			})
+		})
+		.await
+	}
+
+	/// Returns identified peers which are running an incompatible protocol version, with their protocol version.
+	/// Such peers are removed from the routing table and blocked, and listed until they are unblocked.
+	/// Only the most recent mismatches are kept.
+	pub async fn list_protocol_mismatches(&self) -> Result<Vec<(PeerId, String)>> {
+		self.execute_sync(|response_sender| {
+			Box::new(ListProtocolMismatches {
+				response_sender: Some(response_sender),
+			})
+		})
+		.await
+	}
+
+	/// Returns sampled number of connections, oldest first.
+	/// History is empty if connection sampling is not configured.
+	pub async fn connection_history(&self) -> Result<Vec<(Instant, usize)>> {
+		self.execute_sync(|response_sender| {
+			Box::new(GetConnectionHistory {
+				response_sender: Some(response_sender),
+			})
+		})
+		.await
+	}
+
+	/// Returns details of the connected peer, or `None` if the peer is not connected
+	pub async fn peer_info(&self, peer_id: PeerId) -> Result<Option<PeerInfo>> {
+		self.execute_sync(|response_sender| {
+			Box::new(GetPeerInfo {
+				peer_id,
+				response_sender: Some(response_sender),
+			})
+		})
+		.await
+	}
+
+	/// Returns up to `limit` peers from the routing table, with currently connected peers first
+	pub async fn known_peers(&self, limit: usize) -> Result<Vec<(PeerId, Vec<Multiaddr>)>> {
+		self.execute_sync(|response_sender| {
+			Box::new(GetKnownPeers {
+				limit,
+				response_sender: Some(response_sender),
+			})
+		})
+		.await
+	}
+
 	async fn get_multiaddress(&self) -> Result<Vec<Multiaddr>> {
 		self.execute_sync(|response_sender| {
 			Box::new(GetMultiaddress {
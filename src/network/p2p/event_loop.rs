@@ -2,6 +2,7 @@ use color_eyre::{eyre::eyre, Result};
 use futures::StreamExt;
 use libp2p::{
 	autonat::{self, NatStatus},
+	core::transport::ListenerId,
 	dcutr,
 	identify::{self, Info},
 	identity::Keypair,
@@ -13,12 +14,21 @@ use libp2p::{
 	ping,
 	swarm::{
 		dial_opts::{DialOpts, PeerCondition},
-		ConnectionError, SwarmEvent,
+		ConnectionError, ConnectionId, SwarmEvent,
 	},
 	upnp, Multiaddr, PeerId, Swarm,
 };
 use rand::seq::SliceRandom;
-use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
+use std::{
+	collections::{HashMap, HashSet, VecDeque},
+	future,
+	str::FromStr,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
 use tokio::{
 	sync::oneshot,
 	time::{interval_at, Instant, Interval},
@@ -34,7 +44,7 @@ use crate::{
 
 use super::{
 	build_swarm, client::BlockStat, Behaviour, BehaviourEvent, CommandReceiver, EventLoopEntries,
-	QueryChannel, SendableCommand,
+	Listener, PeerInfo, QueryChannel, SendableCommand,
 };
 
 // RelayState keeps track of all things relay related
@@ -67,6 +77,35 @@ impl RelayState {
 	}
 }
 
+/// Interval in which the number of outbound connections is checked against the configured target
+const OUT_PEERS_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Maximum number of connection samples kept in the connection history
+const CONNECTION_HISTORY_SIZE: usize = 360;
+
+/// Maximum number of protocol mismatches kept, since any remote peer can cause one
+const MAX_PROTOCOL_MISMATCHES: usize = 1000;
+
+// PeersState keeps track of established connections, by direction, and of the connected peers
+pub struct PeersState {
+	// target number of outbound connections
+	pub(super) out_peers: Option<u32>,
+	// maximum number of inbound connections
+	in_peers: Option<u32>,
+	outbound: HashSet<ConnectionId>,
+	inbound: HashSet<ConnectionId>,
+	// protocol versions of the blocked peers which are running an incompatible protocol, oldest first
+	pub(super) protocol_mismatches: VecDeque<(PeerId, String)>,
+	// timer that is responsible for checking the outbound connections target
+	timer: Interval,
+	// sampled number of connections, oldest first
+	pub(super) connection_history: VecDeque<(std::time::Instant, usize)>,
+	// details of the connected peers
+	pub(super) connected: HashMap<PeerId, PeerInfo>,
+	// timer that is responsible for sampling the number of connections, if enabled
+	sampling_timer: Option<Interval>,
+}
+
 // BootstrapState keeps track of all things bootstrap related
 struct BootstrapState {
 	// referring to the initial bootstrap process,
@@ -81,18 +120,24 @@ struct EventLoopConfig {
 	identity_data: IdentifyConfig,
 	is_fat_client: bool,
 	kad_record_ttl: TimeToLive,
+	allow_private_ips: bool,
 }
 
 pub struct EventLoop {
 	swarm: Swarm<Behaviour>,
+	command_receiver: CommandReceiver,
 	// Tracking Kademlia events
 	pending_kad_queries: HashMap<QueryId, QueryChannel>,
 	// Tracking swarm events (i.e. peer dialing)
 	pending_swarm_events: HashMap<PeerId, oneshot::Sender<Result<()>>>,
 	relay: RelayState,
 	bootstrap: BootstrapState,
+	peers: PeersState,
 	/// Blocks we monitor for PUT success rate
 	active_blocks: HashMap<u32, BlockStat>,
+	listeners: Vec<Listener>,
+	/// Number of established connections, shared with the client
+	connections: Arc<AtomicUsize>,
 	shutdown: Controller<String>,
 
 	event_loop_config: EventLoopConfig,
@@ -127,18 +172,19 @@ impl EventLoop {
 		id_keys: &Keypair,
 		is_fat_client: bool,
 		is_ws_transport: bool,
+		command_receiver: CommandReceiver,
+		connections: Arc<AtomicUsize>,
 		shutdown: Controller<String>,
-	) -> Self {
+	) -> Result<Self> {
 		let bootstrap_interval = cfg.bootstrap_interval;
 		let peer_id = id_keys.public().to_peer_id();
 		let store = MemoryStore::with_config(peer_id, (&cfg).into());
 
-		let swarm = build_swarm(&cfg, id_keys, store, is_ws_transport)
-			.await
-			.expect("Unable to build swarm.");
+		let swarm = build_swarm(&cfg, id_keys, store, is_ws_transport).await?;
 
-		Self {
+		Ok(Self {
 			swarm,
+			command_receiver,
 			pending_kad_queries: Default::default(),
 			pending_swarm_events: Default::default(),
 			relay: RelayState {
@@ -151,17 +197,40 @@ impl EventLoop {
 				is_startup_done: false,
 				timer: interval_at(Instant::now() + bootstrap_interval, bootstrap_interval),
 			},
+			peers: PeersState {
+				out_peers: cfg.out_peers,
+				in_peers: cfg.in_peers,
+				outbound: Default::default(),
+				inbound: Default::default(),
+				protocol_mismatches: Default::default(),
+				timer: interval_at(
+					Instant::now() + OUT_PEERS_CHECK_INTERVAL,
+					OUT_PEERS_CHECK_INTERVAL,
+				),
+				connection_history: Default::default(),
+				connected: Default::default(),
+				sampling_timer: cfg
+					.connection_sampling_interval
+					.map(|interval| interval_at(Instant::now() + interval, interval)),
+			},
 			active_blocks: Default::default(),
+			listeners: Default::default(),
+			connections,
 			shutdown,
 			event_loop_config: EventLoopConfig {
 				identity_data: cfg.identify,
 				is_fat_client,
 				kad_record_ttl: TimeToLive(cfg.kademlia.kad_record_ttl),
+				allow_private_ips: cfg.allow_private_ips,
 			},
-		}
+		})
+	}
+
+	pub fn local_peer_id(&self) -> &PeerId {
+		self.swarm.local_peer_id()
 	}
 
-	pub async fn run(mut self, metrics: Arc<impl Metrics>, mut command_receiver: CommandReceiver) {
+	pub async fn run(mut self, metrics: Arc<impl Metrics>) {
 		// shutdown will wait as long as this token is not dropped
 		let _delay_token = self
 			.shutdown
@@ -171,7 +240,7 @@ impl EventLoop {
 		loop {
 			tokio::select! {
 				event = self.swarm.next() => self.handle_event(event.expect("Swarm stream should be infinite"), metrics.clone()).await,
-				command = command_receiver.recv() => match command {
+				command = self.command_receiver.recv() => match command {
 					Some(c) => self.handle_command(c).await,
 					//
 					None => {
@@ -180,6 +249,8 @@ impl EventLoop {
 					},
 				},
 				_ = self.bootstrap.timer.tick() => self.handle_periodic_bootstraps(),
+				_ = self.peers.timer.tick(), if self.peers.out_peers.is_some() => self.dial_out_peers(),
+				_ = tick(self.peers.sampling_timer.as_mut()) => self.sample_connections(),
 				// if the shutdown was triggered,
 				// break the loop immediately, proceed to the cleanup phase
 				_ = self.shutdown.triggered_shutdown() => {
@@ -191,6 +262,20 @@ impl EventLoop {
 		self.disconnect_peers();
 	}
 
+	fn update_connections(&self) {
+		let connections = self.swarm.network_info().num_connections() as usize;
+		self.connections.store(connections, Ordering::Relaxed);
+	}
+
+	fn sample_connections(&mut self) {
+		let connections = self.swarm.network_info().num_connections() as usize;
+		let history = &mut self.peers.connection_history;
+		if history.len() == CONNECTION_HISTORY_SIZE {
+			history.pop_front();
+		}
+		history.push_back((std::time::Instant::now(), connections));
+	}
+
 	fn disconnect_peers(&mut self) {
 		let connected_peers: Vec<PeerId> = self.swarm.connected_peers().cloned().collect();
 		// close all active connections with other peers
@@ -256,6 +341,16 @@ impl EventLoop {
 					kad::Event::OutboundQueryProgressed {
 						id, result, stats, ..
 					} => match result {
+						QueryResult::GetRecord(_) if self.is_query_canceled(&id) => {
+							// nobody is waiting for the record anymore, stop querying other peers
+							trace!("Get record query {id:?} canceled");
+							self.pending_kad_queries.remove(&id);
+							if let Some(mut query) =
+								self.swarm.behaviour_mut().kademlia.query_mut(&id)
+							{
+								query.finish();
+							}
+						},
 						QueryResult::GetRecord(result) => match result {
 							Ok(GetRecordOk::FoundRecord(record)) => {
 								if let Some(QueryChannel::GetRecord(ch)) =
@@ -338,6 +433,9 @@ impl EventLoop {
 					trace!(
 						"Identity Received from: {peer_id:?} on listen address: {listen_addrs:?}"
 					);
+					if let Some(peer) = self.peers.connected.get_mut(&peer_id) {
+						peer.agent_version = Some(agent_version.clone());
+					}
 					let incoming_peer_agent_version = match AgentVersion::from_str(&agent_version) {
 						Ok(agent) => agent,
 						Err(e) => {
@@ -346,12 +444,17 @@ impl EventLoop {
 						},
 					};
 					if protocol_version == self.event_loop_config.identity_data.protocol_version {
+						let kademlia_mode =
+							KademliaMode::try_from(incoming_peer_agent_version.kademlia_mode).ok();
+						if let Some(peer) = self.peers.connected.get_mut(&peer_id) {
+							peer.kademlia_mode = kademlia_mode;
+						}
 						// Add peer to routing table only if it's in Kademlia server mode
-						if incoming_peer_agent_version.kademlia_mode
-							== KademliaMode::Server.to_string()
-						{
+						if kademlia_mode == Some(KademliaMode::Server) {
 							trace!("Adding peer {peer_id} to routing table.");
-							for addr in listen_addrs {
+							for addr in listen_addrs.into_iter().filter(|addr| {
+								self.event_loop_config.allow_private_ips || is_global_address(addr)
+							}) {
 								self.swarm
 									.behaviour_mut()
 									.kademlia
@@ -363,6 +466,15 @@ impl EventLoop {
 						debug!("Removing and blocking non-avail peer from routing table. Peer: {peer_id}. Agent: {agent_version}. Protocol: {protocol_version}");
 						self.swarm.behaviour_mut().kademlia.remove_peer(&peer_id);
 						self.swarm.behaviour_mut().blocked_peers.block_peer(peer_id);
+						let blocked_peers = self.swarm.behaviour().blocked_peers.blocked_peers();
+						let mismatches = &mut self.peers.protocol_mismatches;
+						// Drop the previous entry of the peer, and the peers unblocked in the meantime
+						mismatches
+							.retain(|(peer, _)| *peer != peer_id && blocked_peers.contains(peer));
+						mismatches.push_back((peer_id, protocol_version));
+						if mismatches.len() > MAX_PROTOCOL_MISMATCHES {
+							mismatches.pop_front();
+						}
 					}
 				},
 				identify::Event::Sent { peer_id } => {
@@ -393,8 +505,8 @@ impl EventLoop {
 							.swarm
 							.behaviour_mut()
 							.mdns
-							.discovered_nodes()
-							.any(|&p| p == peer_id)
+							.as_mut()
+							.is_some_and(|mdns| mdns.discovered_nodes().any(|&p| p == peer_id))
 						{
 							self.swarm
 								.behaviour_mut()
@@ -437,8 +549,11 @@ impl EventLoop {
 					trace!("Hole punching failed with: {remote_peer_id:#?}. Error: {err:#?}")
 				},
 			},
-			SwarmEvent::Behaviour(BehaviourEvent::Ping(ping::Event { result, .. })) => {
+			SwarmEvent::Behaviour(BehaviourEvent::Ping(ping::Event { peer, result, .. })) => {
 				if let Ok(rtt) = result {
+					if let Some(peer_info) = self.peers.connected.get_mut(&peer) {
+						peer_info.rtt = Some(rtt);
+					}
 					let _ = metrics
 						.record(MetricValue::PingLatency(rtt.as_millis() as f64))
 						.await;
@@ -460,16 +575,61 @@ impl EventLoop {
 			},
 			swarm_event => {
 				match swarm_event {
-					SwarmEvent::NewListenAddr { address, .. } => {
+					SwarmEvent::NewListenAddr {
+						listener_id,
+						address,
+					} => {
 						debug!("Local node is listening on {:?}", address);
+						if let Some(Ok(addresses)) = self.listener_result_mut(listener_id) {
+							addresses.push(address);
+						}
+					},
+					SwarmEvent::ExpiredListenAddr {
+						listener_id,
+						address,
+					} => {
+						debug!("Local node is no longer listening on {:?}", address);
+						if let Some(Ok(addresses)) = self.listener_result_mut(listener_id) {
+							addresses.retain(|listen_address| listen_address != &address);
+						}
+					},
+					SwarmEvent::ListenerClosed {
+						listener_id,
+						reason,
+						..
+					} => {
+						debug!("Listener {listener_id:?} closed: {reason:?}");
+						if let Some(result) = self.listener_result_mut(listener_id) {
+							*result = Err(match reason {
+								Ok(()) => "Listener closed".to_string(),
+								Err(error) => error.to_string(),
+							});
+						}
+					},
+					SwarmEvent::ListenerError { listener_id, error } => {
+						warn!("Listener {listener_id:?} error: {error}");
+						if let Some(result) = self.listener_result_mut(listener_id) {
+							*result = Err(error.to_string());
+						}
 					},
 					SwarmEvent::ConnectionClosed {
 						peer_id,
+						connection_id,
 						endpoint,
 						num_established,
 						cause,
-						..
 					} => {
+						self.peers.inbound.remove(&connection_id);
+						self.peers.outbound.remove(&connection_id);
+						self.update_connections();
+						if num_established == 0 {
+							self.peers.connected.remove(&peer_id);
+						} else if let Some(peer) = self.peers.connected.get_mut(&peer_id) {
+							let address = endpoint.get_remote_address();
+							if let Some(index) = peer.addresses.iter().position(|a| a == address) {
+								peer.addresses.swap_remove(index);
+							}
+						}
 						trace!("Connection closed. PeerID: {peer_id:?}. Address: {:?}. Num established: {num_established:?}. Cause: {cause:?}", endpoint.get_remote_address());
 
 						if let Some(ConnectionError::IO(_)) = cause {
@@ -489,7 +649,29 @@ impl EventLoop {
 							address.to_string()
 						);
 					},
-					SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+					SwarmEvent::ConnectionEstablished {
+						peer_id,
+						connection_id,
+						endpoint,
+						..
+					} => {
+						if endpoint.is_listener() {
+							if self.is_inbound_limit_reached() {
+								debug!("Inbound connections limit reached, closing connection with {peer_id}");
+								self.swarm.close_connection(connection_id);
+								return;
+							}
+							self.peers.inbound.insert(connection_id);
+						} else {
+							self.peers.outbound.insert(connection_id);
+						}
+						self.peers
+							.connected
+							.entry(peer_id)
+							.or_insert_with(PeerInfo::new)
+							.addresses
+							.push(endpoint.get_remote_address().clone());
+						self.update_connections();
 						metrics.count(MetricCounter::ConnectionEstablished).await;
 						// Notify the connections we're waiting on that we've connected successfully
 						if let Some(ch) = self.pending_swarm_events.remove(&peer_id) {
@@ -534,12 +716,88 @@ impl EventLoop {
 		}
 	}
 
+	fn is_inbound_limit_reached(&self) -> bool {
+		self.peers.in_peers.map_or(false, |in_peers| {
+			self.peers.inbound.len() >= in_peers as usize
+		})
+	}
+
+	/// Dials peers from the routing table, if there are less outbound connections than configured
+	fn dial_out_peers(&mut self) {
+		let Some(out_peers) = self.peers.out_peers else {
+			return;
+		};
+		let missing = (out_peers as usize).saturating_sub(self.peers.outbound.len());
+		if missing == 0 {
+			return;
+		}
+
+		let routing_table_peers = self
+			.swarm
+			.behaviour_mut()
+			.kademlia
+			.kbuckets()
+			.flat_map(|bucket| {
+				bucket
+					.iter()
+					.map(|entry| {
+						let peer_id = *entry.node.key.preimage();
+						(peer_id, entry.node.value.clone().into_vec())
+					})
+					.collect::<Vec<_>>()
+			})
+			.collect::<Vec<_>>();
+		let candidates = routing_table_peers
+			.into_iter()
+			.filter(|(peer_id, _)| !self.swarm.is_connected(peer_id))
+			.take(missing)
+			.collect::<Vec<_>>();
+
+		debug!(
+			"Outbound connections below target ({}/{out_peers}), dialing {} peers",
+			self.peers.outbound.len(),
+			candidates.len()
+		);
+		for (peer_id, addresses) in candidates {
+			let opts = DialOpts::peer_id(peer_id)
+				.addresses(addresses)
+				.condition(PeerCondition::Disconnected)
+				.build();
+			if let Err(error) = self.swarm.dial(opts) {
+				trace!("Dialing {peer_id} failed: {error}");
+			}
+		}
+	}
+
+	fn listener_result_mut(
+		&mut self,
+		listener_id: ListenerId,
+	) -> Option<&mut Result<Vec<Multiaddr>, String>> {
+		self.listeners
+			.iter_mut()
+			.find(|listener| listener.id == Some(listener_id))
+			.map(|listener| &mut listener.result)
+	}
+
+	fn is_query_canceled(&self, id: &QueryId) -> bool {
+		matches!(
+			self.pending_kad_queries.get(id),
+			Some(QueryChannel::GetRecord(ch)) if ch.is_closed()
+		)
+	}
+
 	async fn handle_command(&mut self, mut command: SendableCommand) {
+		if command.is_canceled() {
+			trace!("Command receiver dropped, skipping the command");
+			return;
+		}
 		if let Err(err) = command.run(EventLoopEntries::new(
 			&mut self.swarm,
 			&mut self.pending_kad_queries,
 			&mut self.pending_swarm_events,
 			&mut self.active_blocks,
+			&mut self.listeners,
+			&self.peers,
 		)) {
 			command.abort(eyre!(err));
 		}
@@ -658,9 +916,34 @@ impl EventLoop {
 	}
 }
 
+/// Ticks the timer if it is set, otherwise never completes
+async fn tick(timer: Option<&mut Interval>) {
+	match timer {
+		Some(timer) => {
+			timer.tick().await;
+		},
+		None => future::pending().await,
+	}
+}
+
+/// Returns false for private, link-local, loopback and unspecified IP addresses
+fn is_global_address(address: &Multiaddr) -> bool {
+	match address.iter().next() {
+		Some(Protocol::Ip4(ip)) => {
+			!(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified())
+		},
+		Some(Protocol::Ip6(ip)) => {
+			let is_unique_local = (ip.segments()[0] & 0xfe00) == 0xfc00;
+			let is_link_local = (ip.segments()[0] & 0xffc0) == 0xfe80;
+			!(ip.is_loopback() || ip.is_unspecified() || is_unique_local || is_link_local)
+		},
+		_ => true,
+	}
+}
+
 #[cfg(test)]
 mod tests {
-	use crate::network::p2p::event_loop::DHTKey;
+	use crate::network::p2p::event_loop::{is_global_address, DHTKey};
 	use color_eyre::Result;
 	use libp2p::kad::RecordKey;
 
@@ -678,4 +961,27 @@ mod tests {
 		let result: Result<DHTKey> = RecordKey::new(&"123").try_into();
 		_ = result.unwrap_err();
 	}
+
+	#[test]
+	fn global_address() {
+		let global = [
+			"/ip4/52.1.2.3/tcp/37000",
+			"/dns/bootnode.avail.so/tcp/37000",
+			"/ip6/2001:db8::1/udp/37000/quic-v1",
+		];
+		for address in global {
+			assert!(is_global_address(&address.parse().unwrap()), "{address}");
+		}
+
+		let private = [
+			"/ip4/172.18.0.2/tcp/37000",
+			"/ip4/10.0.0.1/tcp/37000",
+			"/ip4/127.0.0.1/tcp/37000",
+			"/ip6/::1/tcp/37000",
+			"/ip6/fd00::1/tcp/37000",
+		];
+		for address in private {
+			assert!(!is_global_address(&address.parse().unwrap()), "{address}");
+		}
+	}
 }
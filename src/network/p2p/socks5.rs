@@ -0,0 +1,237 @@
+//! Transport which dials TCP connections through the SOCKS5 proxy (e.g. Tor).
+//!
+//! Only outbound connections are supported, since the proxy cannot accept
+//! inbound connections on behalf of the light client.
+
+use futures::{
+	future::{BoxFuture, Pending},
+	FutureExt,
+};
+use libp2p::{
+	core::{
+		transport::{ListenerId, TransportError, TransportEvent},
+		Transport,
+	},
+	multiaddr::Protocol,
+	tcp::tokio::TcpStream,
+	Multiaddr,
+};
+use std::{
+	io,
+	net::{IpAddr, SocketAddr},
+	pin::Pin,
+	task::{Context, Poll},
+};
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net,
+};
+
+const VERSION: u8 = 0x05;
+const NO_AUTHENTICATION: u8 = 0x00;
+const CONNECT: u8 = 0x01;
+const RESERVED: u8 = 0x00;
+const SUCCEEDED: u8 = 0x00;
+const ADDRESS_IPV4: u8 = 0x01;
+const ADDRESS_DOMAIN: u8 = 0x03;
+const ADDRESS_IPV6: u8 = 0x04;
+
+/// Destination address, which is resolved by the proxy in case of domain name
+#[derive(Debug, PartialEq)]
+enum Destination {
+	Ip(SocketAddr),
+	Domain(String, u16),
+}
+
+impl Destination {
+	fn from_multiaddr(address: &Multiaddr) -> Option<Self> {
+		let mut protocols = address.iter();
+		let host = protocols.next()?;
+		let Some(Protocol::Tcp(port)) = protocols.next() else {
+			return None;
+		};
+		// Only peer ID is allowed after TCP port
+		if !matches!(protocols.next(), None | Some(Protocol::P2p(_))) {
+			return None;
+		}
+
+		match host {
+			Protocol::Ip4(ip) => Some(Destination::Ip(SocketAddr::new(IpAddr::V4(ip), port))),
+			Protocol::Ip6(ip) => Some(Destination::Ip(SocketAddr::new(IpAddr::V6(ip), port))),
+			Protocol::Dns(name) | Protocol::Dns4(name) | Protocol::Dns6(name) => {
+				Some(Destination::Domain(name.to_string(), port))
+			},
+			_ => None,
+		}
+	}
+
+	fn encode(&self) -> io::Result<Vec<u8>> {
+		let mut encoded = vec![];
+		let port = match self {
+			Destination::Ip(SocketAddr::V4(address)) => {
+				encoded.push(ADDRESS_IPV4);
+				encoded.extend(address.ip().octets());
+				address.port()
+			},
+			Destination::Ip(SocketAddr::V6(address)) => {
+				encoded.push(ADDRESS_IPV6);
+				encoded.extend(address.ip().octets());
+				address.port()
+			},
+			Destination::Domain(name, port) => {
+				let length = u8::try_from(name.len()).map_err(|_| {
+					io::Error::new(io::ErrorKind::InvalidInput, "Domain is too long")
+				})?;
+				encoded.push(ADDRESS_DOMAIN);
+				encoded.push(length);
+				encoded.extend(name.as_bytes());
+				*port
+			},
+		};
+		encoded.extend(port.to_be_bytes());
+		Ok(encoded)
+	}
+}
+
+fn proxy_error(message: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::Other, format!("SOCKS5 proxy: {message}"))
+}
+
+/// Performs SOCKS5 handshake, requesting connection to the destination
+async fn connect(stream: &mut net::TcpStream, destination: &Destination) -> io::Result<()> {
+	stream.write_all(&[VERSION, 1, NO_AUTHENTICATION]).await?;
+	let mut response = [0u8; 2];
+	stream.read_exact(&mut response).await?;
+	if response != [VERSION, NO_AUTHENTICATION] {
+		return Err(proxy_error("authentication method is not supported"));
+	}
+
+	let mut request = vec![VERSION, CONNECT, RESERVED];
+	request.extend(destination.encode()?);
+	stream.write_all(&request).await?;
+
+	let mut response = [0u8; 4];
+	stream.read_exact(&mut response).await?;
+	if response[0] != VERSION {
+		return Err(proxy_error("invalid protocol version"));
+	}
+	if response[1] != SUCCEEDED {
+		return Err(proxy_error(&format!(
+			"connection failed with code {}",
+			response[1]
+		)));
+	}
+
+	// Bound address is not used, but it needs to be consumed
+	let address_length = match response[3] {
+		ADDRESS_IPV4 => 4,
+		ADDRESS_IPV6 => 16,
+		ADDRESS_DOMAIN => stream.read_u8().await? as usize,
+		_ => return Err(proxy_error("invalid bound address type")),
+	};
+	let mut bound_address = vec![0u8; address_length + 2];
+	stream.read_exact(&mut bound_address).await?;
+	Ok(())
+}
+
+#[derive(Clone, Debug)]
+pub struct Socks5Transport {
+	proxy: SocketAddr,
+}
+
+impl Socks5Transport {
+	pub fn new(proxy: SocketAddr) -> Self {
+		Socks5Transport { proxy }
+	}
+}
+
+impl Transport for Socks5Transport {
+	type Output = TcpStream;
+	type Error = io::Error;
+	type ListenerUpgrade = Pending<Result<Self::Output, Self::Error>>;
+	type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+	fn listen_on(
+		&mut self,
+		_id: ListenerId,
+		address: Multiaddr,
+	) -> Result<(), TransportError<Self::Error>> {
+		Err(TransportError::MultiaddrNotSupported(address))
+	}
+
+	fn remove_listener(&mut self, _id: ListenerId) -> bool {
+		false
+	}
+
+	fn dial(&mut self, address: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+		let Some(destination) = Destination::from_multiaddr(&address) else {
+			return Err(TransportError::MultiaddrNotSupported(address));
+		};
+		let proxy = self.proxy;
+		Ok(async move {
+			let mut stream = net::TcpStream::connect(proxy).await?;
+			connect(&mut stream, &destination).await?;
+			Ok(TcpStream(stream))
+		}
+		.boxed())
+	}
+
+	fn dial_as_listener(
+		&mut self,
+		address: Multiaddr,
+	) -> Result<Self::Dial, TransportError<Self::Error>> {
+		self.dial(address)
+	}
+
+	fn poll(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+	) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+		Poll::Pending
+	}
+
+	fn address_translation(&self, _listen: &Multiaddr, _observed: &Multiaddr) -> Option<Multiaddr> {
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Destination;
+	use libp2p::Multiaddr;
+	use std::net::SocketAddr;
+
+	#[test]
+	fn destination_from_multiaddr() {
+		let address: Multiaddr = "/ip4/127.0.0.1/tcp/37000".parse().unwrap();
+		let expected: SocketAddr = "127.0.0.1:37000".parse().unwrap();
+		assert_eq!(
+			Destination::from_multiaddr(&address),
+			Some(Destination::Ip(expected))
+		);
+
+		let address: Multiaddr = "/dns/bootnode.avail.so/tcp/37000".parse().unwrap();
+		assert_eq!(
+			Destination::from_multiaddr(&address),
+			Some(Destination::Domain("bootnode.avail.so".to_string(), 37000))
+		);
+
+		let address: Multiaddr = "/ip4/127.0.0.1/udp/37000/quic-v1".parse().unwrap();
+		assert_eq!(Destination::from_multiaddr(&address), None);
+	}
+
+	#[test]
+	fn destination_encode() {
+		let destination = Destination::Domain("avail".to_string(), 80);
+		assert_eq!(
+			destination.encode().unwrap(),
+			vec![0x03, 5, b'a', b'v', b'a', b'i', b'l', 0, 80]
+		);
+
+		let destination = Destination::Ip("10.0.0.1:443".parse().unwrap());
+		assert_eq!(
+			destination.encode().unwrap(),
+			vec![0x01, 10, 0, 0, 1, 0x01, 0xbb]
+		);
+	}
+}
@@ -1,15 +1,17 @@
 use async_trait::async_trait;
 use avail_subxt::{primitives::Header, utils::H256};
-use codec::Decode;
+use codec::{Decode, Encode};
 use color_eyre::{eyre::eyre, Report, Result};
 use kate_recovery::matrix::{Dimensions, Position};
 use rand::{seq::SliceRandom, thread_rng, Rng};
 use serde::{de, Deserialize};
 use sp_core::bytes::from_hex;
 use std::{
-	collections::HashSet,
+	collections::{HashMap, HashSet, VecDeque},
 	fmt::Display,
+	hash::Hash,
 	sync::{Arc, Mutex},
+	time::Duration,
 };
 use tokio::{
 	sync::broadcast,
@@ -20,17 +22,18 @@ use tracing::{debug, info};
 use crate::{
 	data::Database,
 	network::rpc,
-	types::{GrandpaJustification, RetryConfig, State},
+	types::{GrandpaJustification, QueryCacheConfig, RetryConfig, State, SubscriptionsConfig},
 };
 
 mod client;
+pub mod recording;
 mod subscriptions;
 
-use subscriptions::SubscriptionLoop;
+use subscriptions::{SubscriptionLoop, EVENT_CHANNEL_CAPACITY};
 const CELL_SIZE: usize = 32;
 const PROOF_SIZE: usize = 48;
 pub const CELL_WITH_PROOF_SIZE: usize = CELL_SIZE + PROOF_SIZE;
-pub use subscriptions::Event;
+pub use subscriptions::{Event, HeadPin, HeadPinClient};
 
 pub use client::Client;
 
@@ -80,6 +83,67 @@ impl<'de> Deserialize<'de> for WrappedProof {
 	}
 }
 
+/// Source of the transaction, as expected by the runtime transaction queue API
+#[derive(Clone, Copy, Debug, Encode)]
+pub enum TransactionSource {
+	InBlock,
+	Local,
+	External,
+}
+
+/// Valid transaction information, as returned by `TaggedTransactionQueue_validate_transaction` runtime call
+#[derive(Clone, Debug, Decode, PartialEq)]
+pub struct ValidTransaction {
+	pub priority: u64,
+	pub requires: Vec<Vec<u8>>,
+	pub provides: Vec<Vec<u8>>,
+	pub longevity: u64,
+	pub propagate: bool,
+}
+
+#[derive(Clone, Copy, Debug, Decode, PartialEq)]
+pub enum InvalidTransaction {
+	Call,
+	Payment,
+	Future,
+	Stale,
+	BadProof,
+	AncientBirthBlock,
+	ExhaustsResources,
+	Custom(u8),
+	BadMandatory,
+	MandatoryValidation,
+	BadSigner,
+}
+
+#[derive(Clone, Copy, Debug, Decode, PartialEq)]
+pub enum UnknownTransaction {
+	CannotLookup,
+	NoUnsignedValidator,
+	Custom(u8),
+}
+
+#[derive(Clone, Copy, Debug, Decode, PartialEq)]
+pub enum TransactionValidityError {
+	Invalid(InvalidTransaction),
+	Unknown(UnknownTransaction),
+}
+
+impl Display for TransactionValidityError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			TransactionValidityError::Invalid(error) => write!(f, "Invalid transaction: {error:?}"),
+			TransactionValidityError::Unknown(error) => {
+				write!(f, "Unknown transaction validity: {error:?}")
+			},
+		}
+	}
+}
+
+impl std::error::Error for TransactionValidityError {}
+
+pub type TransactionValidity = std::result::Result<ValidTransaction, TransactionValidityError>;
+
 #[derive(Clone, Debug)]
 pub struct Node {
 	pub host: String,
@@ -87,6 +151,8 @@ pub struct Node {
 	pub spec_name: String,
 	pub spec_version: u32,
 	pub genesis_hash: H256,
+	/// Chain name from the chain specification of the node
+	pub chain_name: String,
 }
 
 impl Node {
@@ -96,6 +162,7 @@ impl Node {
 		spec_name: String,
 		spec_version: u32,
 		genesis_hash: H256,
+		chain_name: String,
 	) -> Self {
 		Self {
 			host,
@@ -103,6 +170,7 @@ impl Node {
 			spec_name,
 			spec_version,
 			genesis_hash,
+			chain_name,
 		}
 	}
 
@@ -125,6 +193,7 @@ impl Default for Node {
 			spec_name: "data-avail".to_string(),
 			spec_version: 0,
 			genesis_hash: Default::default(),
+			chain_name: "{chain_name}".to_string(),
 		}
 	}
 }
@@ -151,6 +220,7 @@ impl Nodes {
 					genesis_hash: Default::default(),
 					spec_version: Default::default(),
 					system_version: Default::default(),
+					chain_name: Default::default(),
 					host: s.to_string(),
 				})
 				.collect(),
@@ -178,6 +248,25 @@ impl Nodes {
 		list
 	}
 
+	/// Orders the list of available Nodes by their score, excluding the host used for the current Subxt client creation.
+	///
+	/// Occasionally, the list is shuffled instead, so nodes with a low score get a chance to improve it.
+	fn ranked(&self, current_host: String, scores: &NodeScores) -> Vec<Node> {
+		if thread_rng().gen_bool(NODE_EXPLORATION_PROBABILITY) {
+			return self.shuffle(current_host);
+		}
+
+		let ranking = scores.ranking(self);
+		let mut list = self
+			.list
+			.iter()
+			.filter(|&Node { host, .. }| self.list.len() <= 1 || host != &current_host)
+			.cloned()
+			.collect::<Vec<Node>>();
+		list.sort_by_key(|node| ranking.iter().position(|(host, _)| host == &node.host));
+		list
+	}
+
 	pub fn iter(&self) -> NodesIterator {
 		NodesIterator {
 			nodes: self,
@@ -186,6 +275,218 @@ impl Nodes {
 	}
 }
 
+/// Smoothing factor of the exponential moving averages used for scoring nodes
+const NODE_SCORE_EMA_ALPHA: f32 = 0.2;
+/// Probability of trying nodes in a random order on failover, instead of by score
+const NODE_EXPLORATION_PROBABILITY: f64 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct NodeScore {
+	/// Exponential moving average of the request round trip time, in seconds
+	rtt: Option<f32>,
+	/// Exponential moving average of the request success rate
+	success_rate: f32,
+}
+
+impl Default for NodeScore {
+	fn default() -> Self {
+		Self {
+			rtt: None,
+			success_rate: 1.0,
+		}
+	}
+}
+
+impl NodeScore {
+	fn record(&mut self, rtt: Duration, success: bool) {
+		let rtt = rtt.as_secs_f32();
+		let success = if success { 1.0 } else { 0.0 };
+		self.rtt = Some(match self.rtt {
+			Some(average) => average + NODE_SCORE_EMA_ALPHA * (rtt - average),
+			None => rtt,
+		});
+		self.success_rate += NODE_SCORE_EMA_ALPHA * (success - self.success_rate);
+	}
+
+	fn value(&self) -> f32 {
+		self.success_rate / (1.0 + self.rtt.unwrap_or_default())
+	}
+}
+
+/// Scores of the RPC nodes, based on the observed request round trip times and success rates
+#[derive(Clone, Default)]
+pub struct NodeScores(Arc<Mutex<HashMap<String, NodeScore>>>);
+
+impl NodeScores {
+	pub fn record(&self, host: &str, rtt: Duration, success: bool) {
+		let mut scores = self.0.lock().unwrap();
+		scores
+			.entry(host.to_string())
+			.or_default()
+			.record(rtt, success);
+	}
+
+	/// Returns hosts of the given nodes with their scores, ordered from the best to the worst
+	pub fn ranking(&self, nodes: &Nodes) -> Vec<(String, f32)> {
+		let scores = self.0.lock().unwrap();
+		let mut ranking = nodes
+			.iter()
+			.map(|Node { host, .. }| {
+				let score = scores.get(host).copied().unwrap_or_default();
+				(host.clone(), score.value())
+			})
+			.collect::<Vec<_>>();
+		ranking.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+		ranking
+	}
+}
+
+/// Least recently used map, with a fixed capacity
+struct LruMap<K, V> {
+	capacity: usize,
+	entries: HashMap<K, V>,
+	order: VecDeque<K>,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> LruMap<K, V> {
+	fn new(capacity: usize) -> Self {
+		LruMap {
+			capacity,
+			entries: HashMap::with_capacity(capacity),
+			order: VecDeque::with_capacity(capacity),
+		}
+	}
+
+	fn touch(&mut self, key: &K) {
+		if let Some(position) = self.order.iter().position(|k| k == key) {
+			self.order.remove(position);
+		}
+		self.order.push_back(key.clone());
+	}
+
+	fn get(&mut self, key: &K) -> Option<V> {
+		let value = self.entries.get(key).cloned()?;
+		self.touch(key);
+		Some(value)
+	}
+
+	fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+		if !self.entries.contains_key(key) {
+			return None;
+		}
+		self.touch(key);
+		self.entries.get_mut(key)
+	}
+
+	fn insert(&mut self, key: K, value: V) -> Option<V> {
+		if self.capacity == 0 {
+			return None;
+		}
+		self.touch(&key);
+		let previous = self.entries.insert(key, value);
+		while self.order.len() > self.capacity {
+			if let Some(evicted) = self.order.pop_front() {
+				self.entries.remove(&evicted);
+			}
+		}
+		previous
+	}
+
+	fn retain(&mut self, f: impl Fn(&K) -> bool) {
+		self.entries.retain(|key, _| f(key));
+		self.order.retain(|key| f(key));
+	}
+}
+
+/// Storage values read at the block, by storage key
+type StorageValues = HashMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// Read-through cache of block hash, header and storage lookups
+#[derive(Clone)]
+pub struct QueryCache(Arc<Mutex<QueryCacheEntries>>);
+
+struct QueryCacheEntries {
+	hashes: LruMap<u32, H256>,
+	headers: LruMap<H256, Header>,
+	storage: LruMap<H256, StorageValues>,
+}
+
+impl QueryCache {
+	/// Creates cache with configured number of entries per lookup type,
+	/// and storage values of the configured number of blocks (zero disables caching)
+	pub fn new(config: QueryCacheConfig) -> Self {
+		QueryCache(Arc::new(Mutex::new(QueryCacheEntries {
+			hashes: LruMap::new(config.query_cache_size),
+			headers: LruMap::new(config.query_cache_size),
+			storage: LruMap::new(config.storage_cache_size),
+		})))
+	}
+
+	pub fn block_hash(&self, block_number: u32) -> Option<H256> {
+		self.0.lock().unwrap().hashes.get(&block_number)
+	}
+
+	/// Caches block hash, purging cached block hashes from the given block number onwards,
+	/// in case they are different from the new one (after reorg).
+	pub fn insert_block_hash(&self, block_number: u32, hash: H256) {
+		let mut entries = self.0.lock().unwrap();
+		let previous = entries.hashes.insert(block_number, hash);
+		if previous.map_or(false, |previous| previous != hash) {
+			debug!("Block hash changed at {block_number}, purging cached block hashes");
+			entries.hashes.retain(|&number| number < block_number);
+			entries.hashes.insert(block_number, hash);
+		}
+	}
+
+	pub fn header(&self, hash: H256) -> Option<Header> {
+		self.0.lock().unwrap().headers.get(&hash)
+	}
+
+	pub fn insert_header(&self, hash: H256, header: Header) {
+		self.0.lock().unwrap().headers.insert(hash, header);
+	}
+
+	/// Returns cached storage value, which is `None` if the storage value didn't exist at the block
+	pub fn storage(&self, block_hash: H256, key: &[u8]) -> Option<Option<Vec<u8>>> {
+		let mut entries = self.0.lock().unwrap();
+		entries.storage.get_mut(&block_hash)?.get(key).cloned()
+	}
+
+	pub fn insert_storage(&self, block_hash: H256, key: Vec<u8>, value: Option<Vec<u8>>) {
+		let mut entries = self.0.lock().unwrap();
+		if let Some(values) = entries.storage.get_mut(&block_hash) {
+			values.insert(key, value);
+		} else {
+			entries
+				.storage
+				.insert(block_hash, HashMap::from([(key, value)]));
+		}
+	}
+}
+
+/// Number of runtimes with cached Wasm code, enough to cover the runtime upgrade
+const RUNTIME_CACHE_CAPACITY: usize = 2;
+
+/// Cache of the runtime Wasm code, which is several MB in size, so only a few runtimes are kept
+#[derive(Clone)]
+pub struct RuntimeCache(Arc<Mutex<LruMap<H256, Vec<u8>>>>);
+
+impl Default for RuntimeCache {
+	fn default() -> Self {
+		RuntimeCache(Arc::new(Mutex::new(LruMap::new(RUNTIME_CACHE_CAPACITY))))
+	}
+}
+
+impl RuntimeCache {
+	pub fn code(&self, code_hash: H256) -> Option<Vec<u8>> {
+		self.0.lock().unwrap().get(&code_hash)
+	}
+
+	pub fn insert_code(&self, code_hash: H256, code: Vec<u8>) {
+		self.0.lock().unwrap().insert(code_hash, code);
+	}
+}
+
 pub struct NodesIterator<'a> {
 	nodes: &'a Nodes,
 	current_index: usize,
@@ -207,13 +508,27 @@ pub async fn init<T: Database>(
 	nodes: &[String],
 	genesis_hash: &str,
 	retry_config: RetryConfig,
+	cache_config: QueryCacheConfig,
+	subscriptions_config: SubscriptionsConfig,
 ) -> Result<(Client, broadcast::Sender<Event>, SubscriptionLoop<T>)> {
-	let rpc_client =
-		Client::new(state.clone(), Nodes::new(nodes), genesis_hash, retry_config).await?;
+	let rpc_client = Client::new(
+		state.clone(),
+		Nodes::new(nodes),
+		genesis_hash,
+		retry_config,
+		QueryCache::new(cache_config),
+	)
+	.await?;
 	// create output channel for RPC Subscription Events
-	let (event_sender, _) = broadcast::channel(1000);
-	let subscriptions =
-		SubscriptionLoop::new(state, db, rpc_client.clone(), event_sender.clone()).await?;
+	let (event_sender, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+	let subscriptions = SubscriptionLoop::new(
+		state,
+		db,
+		rpc_client.clone(),
+		event_sender.clone(),
+		subscriptions_config,
+	)
+	.await?;
 
 	Ok((rpc_client, event_sender, subscriptions))
 }
@@ -277,9 +592,148 @@ pub async fn wait_for_finalized_header(
 	timeout_seconds: u64,
 ) -> Result<Header> {
 	let timeout_seconds = time::Duration::from_secs(timeout_seconds);
-	match timeout(timeout_seconds, rpc_events_receiver.recv()).await {
-		Ok(Ok(rpc::Event::HeaderUpdate { header, .. })) => Ok(header),
+	let first_header = async {
+		loop {
+			match rpc_events_receiver.recv().await {
+				Ok(rpc::Event::HeaderUpdate { header, .. }) => return Ok(header),
+				Ok(_) => continue,
+				Err(error) => return Err(error),
+			}
+		}
+	};
+	match timeout(timeout_seconds, first_header).await {
+		Ok(Ok(header)) => Ok(header),
 		Ok(Err(error)) => Err(eyre!("Failed to receive finalized header: {error}")),
 		Err(_) => Err(eyre!("Timeout on waiting for first finalized header")),
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		InvalidTransaction, LruMap, NodeScore, NodeScores, Nodes, QueryCache, RuntimeCache,
+		TransactionValidity, TransactionValidityError, ValidTransaction, RUNTIME_CACHE_CAPACITY,
+	};
+	use crate::types::QueryCacheConfig;
+	use avail_subxt::utils::H256;
+	use codec::Decode;
+	use std::time::Duration;
+
+	#[test]
+	fn lru_map_evicts_least_recently_used() {
+		let mut map = LruMap::new(2);
+		map.insert(1, "a");
+		map.insert(2, "b");
+		assert_eq!(map.get(&1), Some("a"));
+		map.insert(3, "c");
+		assert_eq!(map.get(&2), None);
+		assert_eq!(map.get(&1), Some("a"));
+		assert_eq!(map.get(&3), Some("c"));
+
+		let mut disabled = LruMap::new(0);
+		disabled.insert(1, "a");
+		assert_eq!(disabled.get(&1), None);
+	}
+
+	#[test]
+	fn query_cache_purges_hashes_on_reorg() {
+		let cache = QueryCache::new(QueryCacheConfig {
+			query_cache_size: 10,
+			storage_cache_size: 0,
+		});
+		for number in 1..=5 {
+			cache.insert_block_hash(number, H256::repeat_byte(number as u8));
+		}
+
+		cache.insert_block_hash(3, H256::repeat_byte(0xff));
+		assert_eq!(cache.block_hash(2), Some(H256::repeat_byte(2)));
+		assert_eq!(cache.block_hash(3), Some(H256::repeat_byte(0xff)));
+		assert_eq!(cache.block_hash(4), None);
+		assert_eq!(cache.block_hash(5), None);
+	}
+
+	#[test]
+	fn query_cache_storage() {
+		let cache = QueryCache::new(QueryCacheConfig {
+			query_cache_size: 0,
+			storage_cache_size: 1,
+		});
+		let (first, second) = (H256::repeat_byte(1), H256::repeat_byte(2));
+		cache.insert_storage(first, vec![1], Some(vec![10]));
+		cache.insert_storage(first, vec![2], None);
+		assert_eq!(cache.storage(first, &[1]), Some(Some(vec![10])));
+		assert_eq!(cache.storage(first, &[2]), Some(None));
+		assert_eq!(cache.storage(first, &[3]), None);
+
+		// Storage values are evicted per block
+		cache.insert_storage(second, vec![1], Some(vec![20]));
+		assert_eq!(cache.storage(first, &[1]), None);
+		assert_eq!(cache.storage(second, &[1]), Some(Some(vec![20])));
+	}
+
+	#[test]
+	fn runtime_cache_code() {
+		let cache = RuntimeCache::default();
+		for byte in 1..=RUNTIME_CACHE_CAPACITY as u8 + 1 {
+			cache.insert_code(H256::repeat_byte(byte), vec![byte]);
+		}
+		assert_eq!(cache.code(H256::repeat_byte(1)), None);
+		assert_eq!(cache.code(H256::repeat_byte(2)), Some(vec![2]));
+		assert_eq!(cache.code(H256::repeat_byte(3)), Some(vec![3]));
+	}
+
+	#[test]
+	fn transaction_validity_decode() {
+		// Ok(ValidTransaction { priority: 1, requires: [], provides: [[1]], longevity: 64, propagate: true })
+		let valid = hex::decode("00010000000000000000040401400000000000000001").unwrap();
+		assert_eq!(
+			TransactionValidity::decode(&mut &valid[..]).unwrap(),
+			Ok(ValidTransaction {
+				priority: 1,
+				requires: vec![],
+				provides: vec![vec![1]],
+				longevity: 64,
+				propagate: true,
+			})
+		);
+
+		// Err(Invalid(Stale))
+		let invalid = hex::decode("010003").unwrap();
+		assert_eq!(
+			TransactionValidity::decode(&mut &invalid[..]).unwrap(),
+			Err(TransactionValidityError::Invalid(InvalidTransaction::Stale))
+		);
+	}
+
+	#[test]
+	fn node_score_record() {
+		let mut score = NodeScore::default();
+		score.record(Duration::from_secs(1), true);
+		assert_eq!(score.rtt, Some(1.0));
+		assert_eq!(score.success_rate, 1.0);
+
+		score.record(Duration::from_secs(2), false);
+		assert!((score.rtt.unwrap() - 1.2).abs() < f32::EPSILON);
+		assert!((score.success_rate - 0.8).abs() < f32::EPSILON);
+	}
+
+	#[test]
+	fn node_scores_ranking() {
+		let nodes = Nodes::new(&[
+			"slow".to_string(),
+			"fast".to_string(),
+			"failing".to_string(),
+		]);
+		let scores = NodeScores::default();
+		scores.record("slow", Duration::from_secs(3), true);
+		scores.record("fast", Duration::from_millis(100), true);
+		scores.record("failing", Duration::from_millis(100), false);
+
+		let ranking = scores
+			.ranking(&nodes)
+			.into_iter()
+			.map(|(host, _)| host)
+			.collect::<Vec<_>>();
+		assert_eq!(ranking, vec!["fast", "failing", "slow"]);
+	}
+}
@@ -6,30 +6,45 @@ use avail_subxt::{
 	utils::H256,
 	AvailConfig,
 };
+use codec::{Decode, Encode};
 use color_eyre::{eyre::eyre, Report, Result};
-use futures::{Stream, TryFutureExt, TryStreamExt};
+use futures::{stream, Stream, TryFutureExt, TryStreamExt};
 use kate_recovery::{data::Cell, matrix::Position};
 use sp_core::{
+	blake2_256,
 	bytes::from_hex,
 	ed25519::{self, Public},
+	storage::well_known_keys::{CODE, DEFAULT_CHILD_STORAGE_KEY_PREFIX},
+	twox_128, Bytes,
+};
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
 };
-use std::sync::{Arc, Mutex};
 use subxt::{
-	rpc::{types::BlockNumber, RpcParams},
+	rpc::{
+		types::{BlockNumber, ChainBlockExtrinsic},
+		RpcParams,
+	},
 	rpc_params,
 	storage::StorageKey,
 	tx::{PairSigner, SubmittableExtrinsic},
 	utils::AccountId32,
 };
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use tokio_retry::Retry;
 use tokio_stream::StreamExt;
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 
-use super::{Node, Nodes, Subscription, WrappedProof, CELL_WITH_PROOF_SIZE};
+use super::{
+	Event, Node, NodeScores, Nodes, QueryCache, RuntimeCache, Subscription, TransactionSource,
+	TransactionValidity, WrappedProof, CELL_WITH_PROOF_SIZE,
+};
 use crate::{
 	consts::ExpectedNodeVariant,
 	types::{RetryConfig, RuntimeVersion, State, DEV_FLAG_GENHASH},
+	utils::is_runtime_upgraded,
 };
 
 #[derive(Clone)]
@@ -37,8 +52,11 @@ pub struct Client {
 	subxt_client: Arc<RwLock<avail::Client>>,
 	state: Arc<Mutex<State>>,
 	nodes: Nodes,
+	scores: NodeScores,
 	retry_config: RetryConfig,
 	expected_genesis_hash: String,
+	cache: QueryCache,
+	runtime_cache: RuntimeCache,
 }
 
 impl Client {
@@ -47,6 +65,7 @@ impl Client {
 		nodes: Nodes,
 		expected_genesis_hash: &str,
 		retry_config: RetryConfig,
+		cache: QueryCache,
 	) -> Result<Self> {
 		// try and connect appropriate Node from the provided list
 		// will do retries with the provided Retry Config
@@ -68,8 +87,11 @@ impl Client {
 			subxt_client: Arc::new(RwLock::new(client)),
 			state,
 			nodes,
+			scores: Default::default(),
 			retry_config,
 			expected_genesis_hash: expected_genesis_hash.to_string(),
+			cache,
+			runtime_cache: Default::default(),
 		})
 	}
 
@@ -118,12 +140,15 @@ impl Client {
 			));
 		}
 
+		let chain_name = client.rpc().system_chain().await?;
+
 		let variant = Node::new(
 			host.to_string(),
 			system_version,
 			runtime_version.spec_name,
 			runtime_version.spec_version,
 			genesis_hash,
+			chain_name,
 		);
 
 		Ok((client, variant))
@@ -165,7 +190,12 @@ impl Client {
 	{
 		// try and execute the passed function, use the Retry strategy if needed
 		if let Ok(result) = Retry::spawn(self.retry_config.clone(), move || async move {
-			f(self.current_client().await).await
+			let started_at = Instant::now();
+			let result = f(self.current_client().await).await;
+			let host = self.state.lock().unwrap().connected_node.host.clone();
+			self.scores
+				.record(&host, started_at.elapsed(), result.is_ok());
+			result
 		})
 		.await
 		{
@@ -178,8 +208,9 @@ impl Client {
 			"Executing RPC call with host: {} failed. Trying to create a new RPC connection.",
 			connected_node.host
 		);
-		// shuffle nodes, if possible
-		let nodes = self.nodes.shuffle(connected_node.host);
+		// order nodes by their score, if possible
+		debug!(ranking = ?self.node_ranking(), "Current RPC nodes ranking");
+		let nodes = self.nodes.ranked(connected_node.host, &self.scores);
 		// go through available Nodes, try to connect, Retry connecting if needed
 		let (client, node, result) = Retry::spawn(self.retry_config.clone(), move || {
 			let nodes = nodes.clone();
@@ -257,11 +288,20 @@ impl Client {
 		}
 	}
 
+	/// Returns RPC node hosts ordered by their score, based on the observed round trip times and success rates
+	pub fn node_ranking(&self) -> Vec<(String, f32)> {
+		self.scores.ranking(&self.nodes)
+	}
+
 	pub async fn current_client(&self) -> avail::Client {
 		self.subxt_client.read().await.clone()
 	}
 
 	pub async fn get_block_hash(&self, block_number: u32) -> Result<H256> {
+		if let Some(hash) = self.cache.block_hash(block_number) {
+			return Ok(hash);
+		}
+
 		let hash = self
 			.with_retries(|client| async move {
 				client
@@ -272,15 +312,33 @@ impl Client {
 			.await?
 			.ok_or_else(|| eyre!("Block with number: {} not found", block_number))?;
 
+		// Only verified finalized block hashes are cached, since others can change on reorg
+		let verified = self.state.lock().unwrap().header_verified.clone();
+		let is_verified = verified.map_or(false, |verified| block_number <= verified.last);
+		if is_verified {
+			self.cache.insert_block_hash(block_number, hash);
+		}
+
 		Ok(hash)
 	}
 
+	/// Caches hash of the block with verified finality, purging stale cached block hashes after reorg
+	pub fn cache_finalized_block_hash(&self, block_number: u32, hash: H256) {
+		self.cache.insert_block_hash(block_number, hash);
+	}
+
 	pub async fn get_header_by_hash(&self, block_hash: H256) -> Result<Header> {
+		if let Some(header) = self.cache.header(block_hash) {
+			return Ok(header);
+		}
+
 		let header = self
 			.with_retries(|client| async move { client.rpc().header(Some(block_hash)).await })
 			.await?
 			.ok_or_else(|| eyre!("Block Header with hash: {:?} not found", block_hash))?;
 
+		self.cache.insert_header(block_hash, header.clone());
+
 		Ok(header)
 	}
 
@@ -309,6 +367,13 @@ impl Client {
 		Ok(head)
 	}
 
+	/// Gets the header of the best block, which is not necessarily finalized
+	pub async fn get_best_header(&self) -> Result<Header> {
+		self.with_retries(|client| async move { client.rpc().header(None).await })
+			.await?
+			.ok_or_else(|| eyre!("Best block header not found"))
+	}
+
 	pub async fn get_chain_head_header(&self) -> Result<Header> {
 		let finalized_hash = self.get_finalized_head_hash().await?;
 		self.get_header_by_hash(finalized_hash).await
@@ -383,6 +448,299 @@ impl Client {
 		Ok(res)
 	}
 
+	pub async fn get_runtime_version_at(&self, block_hash: H256) -> Result<RuntimeVersion> {
+		let res: RuntimeVersion = self
+			.with_retries(|client| async move {
+				client
+					.rpc()
+					.request("state_getRuntimeVersion", rpc_params![block_hash])
+					.await
+			})
+			.await?;
+
+		Ok(res)
+	}
+
+	/// Returns runtime Wasm blob (`:code` storage) at the given block.
+	/// Blob is cached by its hash, and fetched blob is checked against the storage hash returned by the node.
+	pub async fn get_runtime_code(&self, block_hash: H256) -> Result<Vec<u8>> {
+		let code_hash: Option<H256> = self
+			.with_retries(|client| async move {
+				client
+					.rpc()
+					.request(
+						"state_getStorageHash",
+						rpc_params![Bytes(CODE.to_vec()), block_hash],
+					)
+					.await
+			})
+			.await?;
+		let code_hash = code_hash.ok_or_else(|| eyre!("Runtime code not found at {block_hash}"))?;
+		if let Some(code) = self.runtime_cache.code(code_hash) {
+			return Ok(code);
+		}
+
+		let code = self
+			.with_retries(
+				|client| async move { client.rpc().storage(CODE, Some(block_hash)).await },
+			)
+			.await?
+			.ok_or_else(|| eyre!("Runtime code not found at {block_hash}"))?
+			.0;
+		if H256::from(blake2_256(&code)) != code_hash {
+			return Err(eyre!(
+				"Runtime code at {block_hash} does not match its hash"
+			));
+		}
+
+		debug!(?code_hash, size = code.len(), "Caching runtime code");
+		self.runtime_cache.insert_code(code_hash, code.clone());
+
+		Ok(code)
+	}
+
+	/// Reads storage value at the block, which is cached per block.
+	/// Returns `None` if there is no value under the given key.
+	pub async fn get_storage(&self, block_hash: H256, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+		if let Some(value) = self.cache.storage(block_hash, &key) {
+			return Ok(value);
+		}
+
+		let storage_key = &key;
+		let value = self
+			.with_retries(|client| async move {
+				client.rpc().storage(storage_key, Some(block_hash)).await
+			})
+			.await?
+			.map(|data| data.0);
+		self.cache.insert_storage(block_hash, key, value.clone());
+		Ok(value)
+	}
+
+	/// Reads storage values of multiple keys at the block, returned in the order of the keys.
+	/// Values which are not cached are fetched in a single request.
+	pub async fn get_storage_many(
+		&self,
+		block_hash: H256,
+		keys: Vec<Vec<u8>>,
+	) -> Result<Vec<Option<Vec<u8>>>> {
+		let values = keys
+			.iter()
+			.map(|key| self.cache.storage(block_hash, key))
+			.collect::<Vec<_>>();
+
+		let missing_keys = keys
+			.iter()
+			.zip(values.iter())
+			.filter(|(_, value)| value.is_none())
+			.map(|(key, _)| key.clone())
+			.collect::<Vec<_>>();
+		if missing_keys.is_empty() {
+			return Ok(values.into_iter().flatten().collect());
+		}
+
+		let query_keys = &missing_keys;
+		let change_sets = self
+			.with_retries(|client| async move {
+				client
+					.rpc()
+					.query_storage_at(query_keys.iter().map(|key| &key[..]), Some(block_hash))
+					.await
+			})
+			.await?;
+
+		// Keys without value can be omitted from the response
+		let mut fetched = change_sets
+			.into_iter()
+			.flat_map(|change_set| change_set.changes)
+			.map(|(key, data)| (key.0, data.map(|data| data.0)))
+			.collect::<HashMap<_, _>>();
+		for key in missing_keys {
+			let value = fetched.remove(&key).flatten();
+			self.cache
+				.insert_storage(block_hash, key.clone(), value.clone());
+			fetched.insert(key, value);
+		}
+
+		Ok(keys
+			.iter()
+			.zip(values)
+			.map(|(key, value)| value.unwrap_or_else(|| fetched.get(key).cloned().flatten()))
+			.collect())
+	}
+
+	/// Reads storage value from the default child trie at the block.
+	/// Returns an error if the child trie doesn't exist, and `None` if there is no value under the key.
+	pub async fn get_child_storage(
+		&self,
+		block_hash: H256,
+		child_key: Vec<u8>,
+		key: Vec<u8>,
+	) -> Result<Option<Vec<u8>>> {
+		let prefixed_key = [DEFAULT_CHILD_STORAGE_KEY_PREFIX, &child_key].concat();
+		// Child trie root is stored under the prefixed key in the top-level trie
+		if self
+			.get_storage(block_hash, prefixed_key.clone())
+			.await?
+			.is_none()
+		{
+			return Err(eyre!(
+				"Child trie {} not found at {block_hash}",
+				hex::encode(&child_key)
+			));
+		}
+
+		let (prefixed_key, key) = (&prefixed_key, &key);
+		let value: Option<Bytes> = self
+			.with_retries(|client| async move {
+				client
+					.rpc()
+					.request(
+						"childstate_getStorage",
+						rpc_params![Bytes(prefixed_key.clone()), Bytes(key.clone()), block_hash],
+					)
+					.await
+			})
+			.await?;
+		Ok(value.map(|value| value.0))
+	}
+
+	/// Returns block timestamp in milliseconds, read from the `Timestamp::Now` storage at the block.
+	/// Timestamp is cached together with the other storage values of the block.
+	pub async fn get_block_timestamp(&self, block_hash: H256) -> Result<Option<u64>> {
+		let key = [twox_128(b"Timestamp"), twox_128(b"Now")].concat();
+		let Some(value) = self.get_storage(block_hash, key).await? else {
+			return Ok(None);
+		};
+		let timestamp = u64::decode(&mut &value[..])
+			.map_err(|error| eyre!("Cannot decode timestamp at {block_hash}: {error}"))?;
+		Ok(Some(timestamp))
+	}
+
+	/// Waits until the transaction with the given hash is included in one of the finalized blocks,
+	/// starting with the latest finalized block, and then the ones received as header update events.
+	/// Blocks skipped because the receiver lagged behind are checked once the next header is received.
+	/// Block bodies are fetched only while waiting. Returns hash of the block which includes the transaction.
+	pub async fn await_transaction_in_block(
+		&self,
+		mut event_receiver: broadcast::Receiver<Event>,
+		tx_hash: H256,
+		timeout: Duration,
+	) -> Result<H256> {
+		let find_transaction = async {
+			// Transaction could be included before the first header update is received
+			let finalized_hash = self.get_finalized_head_hash().await?;
+			if self.is_included(finalized_hash, tx_hash).await? {
+				return Ok(finalized_hash);
+			}
+			let mut checked = self.get_header_by_hash(finalized_hash).await?.number;
+
+			loop {
+				let header = match event_receiver.recv().await {
+					Ok(Event::HeaderUpdate { header, .. }) => header,
+					Ok(_) => continue,
+					Err(broadcast::error::RecvError::Lagged(skipped)) => {
+						warn!(skipped, "Headers are skipped while waiting for transaction");
+						continue;
+					},
+					Err(error) => return Err(eyre!("Cannot receive header: {error}")),
+				};
+				if header.number <= checked {
+					continue;
+				}
+
+				// check the blocks skipped since the last checked one
+				for block_number in (checked + 1)..header.number {
+					let block_hash = self.get_block_hash(block_number).await?;
+					if self.is_included(block_hash, tx_hash).await? {
+						return Ok(block_hash);
+					}
+				}
+
+				let block_hash: H256 = Encode::using_encoded(&header, blake2_256).into();
+				if self.is_included(block_hash, tx_hash).await? {
+					return Ok(block_hash);
+				}
+				checked = header.number;
+			}
+		};
+
+		tokio::time::timeout(timeout, find_transaction)
+			.await
+			.map_err(|_| eyre!("Transaction {tx_hash:?} is not included in {timeout:?}"))?
+	}
+
+	/// Checks if the block body contains the transaction with the given hash
+	async fn is_included(&self, block_hash: H256, tx_hash: H256) -> Result<bool> {
+		let block = self
+			.with_retries(|client| async move { client.rpc().block(Some(block_hash)).await })
+			.await?;
+		Ok(block.is_some_and(|block| {
+			block
+				.block
+				.extrinsics
+				.iter()
+				.any(|extrinsic| extrinsic_hash(extrinsic) == tx_hash)
+		}))
+	}
+
+	/// Returns stream of runtime upgrades, detected in the digest of the finalized headers received as header update events.
+	/// Each upgrade carries the hash of the block which enacted it, and the new runtime version.
+	pub fn runtime_upgrades(
+		&self,
+		event_receiver: broadcast::Receiver<Event>,
+	) -> impl Stream<Item = Result<(H256, RuntimeVersion)>> {
+		let client = self.clone();
+		stream::unfold(event_receiver, move |mut event_receiver| {
+			let client = client.clone();
+			async move {
+				loop {
+					let header = match event_receiver.recv().await {
+						Ok(Event::HeaderUpdate { header, .. }) => header,
+						Ok(_) => continue,
+						Err(broadcast::error::RecvError::Lagged(skipped)) => {
+							warn!(
+								skipped,
+								"Headers are skipped while watching for runtime upgrades"
+							);
+							continue;
+						},
+						Err(broadcast::error::RecvError::Closed) => return None,
+					};
+					if !is_runtime_upgraded(&header) {
+						continue;
+					}
+
+					let block_hash: H256 = Encode::using_encoded(&header, blake2_256).into();
+					let runtime_version = client
+						.get_runtime_version_at(block_hash)
+						.await
+						.map(|runtime_version| (block_hash, runtime_version));
+					return Some((runtime_version, event_receiver));
+				}
+			}
+		})
+	}
+
+	/// Returns SCALE encoded extrinsic at the given index of the block, if both exist.
+	/// Node returns the whole block body, only the requested extrinsic is kept.
+	pub async fn get_extrinsic(&self, block_hash: H256, index: u32) -> Result<Option<Vec<u8>>> {
+		let block = self
+			.with_retries(|client| async move { client.rpc().block(Some(block_hash)).await })
+			.await?;
+
+		let extrinsic = block.and_then(|block| {
+			block
+				.block
+				.extrinsics
+				.into_iter()
+				.nth(index as usize)
+				// Extrinsic bytes are received without the length prefix
+				.map(|extrinsic| extrinsic.0.encode())
+		});
+		Ok(extrinsic)
+	}
+
 	pub async fn get_validator_set_by_block_number(&self, block_num: u32) -> Result<Vec<Public>> {
 		let hash = self.get_block_hash(block_num).await?;
 		self.get_validator_set_by_hash(hash).await
@@ -424,6 +782,75 @@ impl Client {
 		Ok(res)
 	}
 
+	/// Validates transaction against the best block state,
+	/// using runtime `TaggedTransactionQueue_validate_transaction` call.
+	pub async fn validate_transaction(&self, tx_bytes: Vec<u8>) -> Result<TransactionValidity> {
+		let best_hash = self.get_best_block_hash().await?;
+
+		let mut call_parameters = TransactionSource::External.encode();
+		call_parameters.extend(&tx_bytes);
+		call_parameters.extend(best_hash.encode());
+
+		let res = self
+			.with_retries(|client| {
+				let call_parameters = &call_parameters;
+				async move {
+					client
+						.rpc()
+						.state_call(
+							"TaggedTransactionQueue_validate_transaction",
+							Some(&call_parameters[..]),
+							Some(best_hash),
+						)
+						.await
+				}
+			})
+			.await?;
+
+		TransactionValidity::decode(&mut &res[..])
+			.map_err(|error| eyre!("Cannot decode transaction validity: {error}"))
+	}
+
+	async fn get_best_block_hash(&self) -> Result<H256> {
+		self.with_retries(|client| async move { client.rpc().block_hash(None).await })
+			.await?
+			.ok_or_else(|| eyre!("Best block hash is not available"))
+	}
+
+	/// Calls the runtime API method at the best block, with SCALE encoded arguments.
+	/// Best block hash is resolved once, so retried calls are made against the same block.
+	/// Returns the hash of the block used, along with the SCALE encoded result.
+	pub async fn runtime_call_best(&self, method: &str, args: Vec<u8>) -> Result<(H256, Vec<u8>)> {
+		let best_hash = self.get_best_block_hash().await?;
+		let args = &args;
+		let res = self
+			.with_retries(|client| async move {
+				client
+					.rpc()
+					.state_call(method, Some(&args[..]), Some(best_hash))
+					.await
+			})
+			.await?;
+		Ok((best_hash, res))
+	}
+
+	/// Signs the call and returns encoded extrinsic
+	pub async fn create_signed<Call: subxt::tx::TxPayload>(
+		&self,
+		call: &Call,
+		signer: &PairSigner<AvailConfig, Pair>,
+		other_params: avail_subxt::primitives::AvailExtrinsicParams,
+	) -> Result<Vec<u8>> {
+		let extrinsic = self
+			.with_retries(|client| {
+				let other_params = other_params.clone();
+				async move { client.tx().create_signed(call, signer, other_params).await }
+			})
+			.await?;
+
+		Ok(extrinsic.into_encoded())
+	}
+
 	pub async fn submit_signed_and_wait_for_finalized<Call: subxt::tx::TxPayload>(
 		&self,
 		call: &Call,
@@ -541,3 +968,31 @@ impl Client {
 		Ok(gen_hash)
 	}
 }
+
+/// Returns the transaction hash of the extrinsic, as returned on submission.
+/// Hash is calculated over the SCALE encoding, which includes the length prefix omitted in the block body.
+fn extrinsic_hash(extrinsic: &ChainBlockExtrinsic) -> H256 {
+	Encode::using_encoded(&extrinsic.0, blake2_256).into()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::extrinsic_hash;
+	use avail_subxt::utils::H256;
+	use hex_literal::hex;
+	use subxt::rpc::types::ChainBlockExtrinsic;
+
+	#[test]
+	fn extrinsic_hash_includes_length_prefix() {
+		// Timestamp inherent, as returned in the block body by the node
+		let extrinsic: ChainBlockExtrinsic =
+			serde_json::from_str(r#""0x280403000b000a151d8b01""#).unwrap();
+		assert_eq!(extrinsic.0, hex!("0403000b000a151d8b01"));
+		assert_eq!(
+			extrinsic_hash(&extrinsic),
+			H256(hex!(
+				"521d135887f75c37f023543b444cd215cfe144f027bfbe18b385bc0ec3686b7d"
+			))
+		);
+	}
+}
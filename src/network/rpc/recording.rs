@@ -0,0 +1,215 @@
+//! Recording of the subscription events into the file, and replaying them for debugging.
+//!
+//! Events are recorded as JSON lines, each with the time elapsed since the recording started.
+//! Instants are not serializable, so the produced time, and the received time of the header update,
+//! are recorded as offsets and replayed relative to the start of the replay.
+
+use color_eyre::{eyre::WrapErr, Result};
+use futures::{stream, Stream};
+use serde::{Deserialize, Serialize};
+use std::{
+	path::{Path, PathBuf},
+	time::{Duration, Instant},
+};
+use tokio::{
+	fs::File,
+	io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter},
+	sync::broadcast::{self, error::RecvError},
+};
+use tracing::{error, info, warn};
+
+use super::Event;
+
+#[derive(Serialize, Deserialize)]
+struct RecordedEvent {
+	/// Milliseconds elapsed since the start of the recording
+	elapsed_ms: u64,
+	/// Milliseconds elapsed since the start of the recording, when the header was received
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	received_ms: Option<u64>,
+	event: Event,
+}
+
+/// Writes received events to the file, until the event channel is closed.
+/// Existing file is truncated, since recorded times are relative to the start of a single recording.
+/// Writes are buffered, and flushed once all received events are written.
+pub async fn record(path: PathBuf, mut event_receiver: broadcast::Receiver<Event>) {
+	let file = match File::create(&path).await {
+		Ok(file) => file,
+		Err(error) => {
+			error!(
+				"Cannot open event recording file {}: {error}",
+				path.display()
+			);
+			return;
+		},
+	};
+	info!("Recording events to {}", path.display());
+
+	let started_at = Instant::now();
+	let mut writer = BufWriter::new(file);
+	loop {
+		let event = match event_receiver.recv().await {
+			Ok(event) => event,
+			Err(RecvError::Lagged(skipped)) => {
+				warn!(skipped, "Event recording is lagging, events are skipped");
+				continue;
+			},
+			Err(RecvError::Closed) => break,
+		};
+
+		let elapsed_ms = |at: Instant| at.saturating_duration_since(started_at).as_millis() as u64;
+		let recorded = RecordedEvent {
+			elapsed_ms: elapsed_ms(event.produced_at()),
+			received_ms: event.received_at().map(elapsed_ms),
+			event,
+		};
+		if let Err(error) = write_event(&mut writer, &recorded).await {
+			error!("Cannot record event: {error:#}");
+			return;
+		}
+		if event_receiver.is_empty() {
+			if let Err(error) = writer.flush().await {
+				error!("Cannot flush event recording: {error}");
+				return;
+			}
+		}
+	}
+	_ = writer.flush().await;
+}
+
+async fn write_event(writer: &mut BufWriter<File>, recorded: &RecordedEvent) -> Result<()> {
+	let mut line = serde_json::to_vec(recorded)?;
+	line.push(b'\n');
+	writer
+		.write_all(&line)
+		.await
+		.wrap_err("Cannot write to the event recording file")
+}
+
+/// Reads recorded events from the file.
+/// Recorded times are replayed as instants relative to the moment this function is called.
+pub async fn replay(
+	path: impl AsRef<Path>,
+) -> Result<impl Stream<Item = Result<(Instant, Event)>>> {
+	let file = File::open(path.as_ref())
+		.await
+		.wrap_err("Cannot open event recording file")?;
+	let replayed_at = Instant::now();
+	let lines = BufReader::new(file).lines();
+
+	Ok(stream::unfold(lines, move |mut lines| async move {
+		let item = match lines.next_line().await {
+			Ok(None) => return None,
+			Ok(Some(line)) => serde_json::from_str::<RecordedEvent>(&line)
+				.wrap_err("Cannot decode recorded event")
+				.map(|mut recorded| {
+					let at = replayed_at + Duration::from_millis(recorded.elapsed_ms);
+					recorded.event.set_produced_at(at);
+					if let Some(received_ms) = recorded.received_ms {
+						recorded
+							.event
+							.set_received_at(replayed_at + Duration::from_millis(received_ms));
+					}
+					(at, recorded.event)
+				}),
+			Err(error) => Err(error).wrap_err("Cannot read event recording file"),
+		};
+		Some((item, lines))
+	}))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{record, replay};
+	use crate::{network::rpc::Event, types::SyncState};
+	use avail_subxt::{
+		api::runtime_types::avail_core::{
+			data_lookup::compact::CompactDataLookup,
+			header::extension::{v3, HeaderExtension},
+			kate_commitment::v3::KateCommitment,
+		},
+		primitives::Header,
+		utils::H256,
+	};
+	use futures::StreamExt;
+	use std::time::{Duration, Instant};
+	use subxt::config::substrate::Digest;
+	use tokio::sync::broadcast;
+
+	fn header() -> Header {
+		Header {
+			parent_hash: H256::default(),
+			number: 1,
+			state_root: H256::default(),
+			extrinsics_root: H256::default(),
+			extension: HeaderExtension::V3(v3::HeaderExtension {
+				commitment: KateCommitment::default(),
+				app_lookup: CompactDataLookup {
+					size: 0,
+					index: vec![],
+				},
+			}),
+			digest: Digest { logs: vec![] },
+		}
+	}
+
+	#[tokio::test]
+	async fn record_and_replay() {
+		let path = std::env::temp_dir().join(format!(
+			"avail-light-events-{}.jsonl",
+			rand::random::<u64>()
+		));
+		// Previous recording is replaced
+		std::fs::write(&path, "previous recording\n").unwrap();
+
+		let (sender, receiver) = broadcast::channel(10);
+		// Times in the future are recorded relative to the start of the recording
+		let received_at = Instant::now() + Duration::from_secs(1);
+		sender
+			.send(Event::HeaderUpdate {
+				header: header(),
+				received_at,
+				produced_at: received_at + Duration::from_millis(500),
+			})
+			.unwrap();
+		sender
+			.send(Event::FinalityLagging {
+				lag: 5,
+				produced_at: Instant::now(),
+			})
+			.unwrap();
+		sender
+			.send(Event::SyncStateChanged {
+				from: SyncState::Idle,
+				to: SyncState::Following,
+				produced_at: Instant::now(),
+			})
+			.unwrap();
+		drop(sender);
+		record(path.clone(), receiver).await;
+
+		let events = replay(&path).await.unwrap().collect::<Vec<_>>().await;
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!(events.len(), 3);
+		let (at, event) = events[0].as_ref().unwrap();
+		assert_eq!(*at, event.produced_at());
+		let received_at = event.received_at().unwrap();
+		let latency = event.produced_at() - received_at;
+		assert!(latency >= Duration::from_millis(499) && latency <= Duration::from_millis(500));
+		let (at, event) = events[1].as_ref().unwrap();
+		assert!(matches!(event, Event::FinalityLagging { lag: 5, .. }));
+		assert_eq!(*at, event.produced_at());
+		assert_eq!(event.received_at(), None);
+		let (_, event) = events[2].as_ref().unwrap();
+		assert!(matches!(
+			event,
+			Event::SyncStateChanged {
+				from: SyncState::Idle,
+				to: SyncState::Following,
+				..
+			}
+		));
+	}
+}
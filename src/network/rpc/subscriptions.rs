@@ -1,57 +1,343 @@
-use avail_subxt::primitives::{grandpa::AuthorityId, Header};
+use async_trait::async_trait;
+use avail_subxt::{
+	primitives::{grandpa::AuthorityId, Header},
+	utils::H256,
+};
 use codec::Encode;
 use color_eyre::{eyre::eyre, Result};
+use mockall::automock;
+use serde::{Deserialize, Serialize};
 use sp_core::{
 	blake2_256,
 	ed25519::{self, Public},
 };
 use std::{
+	collections::{HashMap, VecDeque},
 	sync::{Arc, Mutex},
-	time::Instant,
+	time::{Duration, Instant, SystemTime},
 };
-use tokio::sync::broadcast::Sender;
+use tokio::sync::{broadcast::Sender, Notify};
 use tokio_stream::StreamExt;
-use tracing::{debug, info, trace};
+use tracing::{debug, error, info, trace, warn};
 
-use super::{Client, Subscription};
+use super::{LruMap, Subscription};
 use crate::{
 	data::Database,
 	data::{FinalitySyncCheckpoint, Key},
-	finality::{check_finality, ValidatorSet},
-	types::{GrandpaJustification, OptionBlockRange, State},
+	finality::{check_finality, JustificationRejection, ValidatorSet},
+	logging::TaskKind,
+	network::rpc,
+	types::{GrandpaJustification, OptionBlockRange, State, SubscriptionsConfig, SyncState},
 	utils::filter_auth_set_changes,
 };
 
-#[derive(Clone, Debug)]
+/// Events produced by the subscription loop.
+/// Each event carries the time it was produced (not serialized), e.g. the header update is produced once the header is verified,
+/// while `received_at` is the time header was received from the node, which allows measuring finalization latency.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Event {
 	HeaderUpdate {
 		header: Header,
+		#[serde(skip, default = "Instant::now")]
 		received_at: Instant,
+		#[serde(skip, default = "Instant::now")]
+		produced_at: Instant,
+	},
+	/// Finalized head lags behind the best block of the node more than the configured threshold.
+	FinalityLagging {
+		lag: u64,
+		#[serde(skip, default = "Instant::now")]
+		produced_at: Instant,
+	},
+	/// Finalized head has caught up with the best block of the node, within the configured threshold.
+	FinalityRecovered {
+		#[serde(skip, default = "Instant::now")]
+		produced_at: Instant,
+	},
+	/// Event channel is nearly full, because some of the consumers are not keeping up.
+	ConsumerLagging {
+		queued: usize,
+		capacity: usize,
+		#[serde(skip, default = "Instant::now")]
+		produced_at: Instant,
+	},
+	/// Light client has moved to the different sync phase.
+	SyncStateChanged {
+		from: SyncState,
+		to: SyncState,
+		#[serde(skip, default = "Instant::now")]
+		produced_at: Instant,
+	},
+	/// Finality has advanced by more than one block at once.
+	/// Sent after the header updates of all blocks in the range, with their hashes in ascending order.
+	NewFinalizedRange {
+		from: u32,
+		to: u32,
+		hashes: Vec<H256>,
+		#[serde(skip, default = "Instant::now")]
+		produced_at: Instant,
+	},
+	/// There are connected peers, but none of them is in Kademlia server mode, so DHT cannot be queried.
+	NoServerPeers {
+		connected: usize,
+		#[serde(skip, default = "Instant::now")]
+		produced_at: Instant,
 	},
+	/// Peers in Kademlia server mode are connected again, after being reported missing.
+	ServerPeersRecovered {
+		#[serde(skip, default = "Instant::now")]
+		produced_at: Instant,
+	},
+	/// Supervised light client task has panicked.
+	TaskCrashed {
+		task: TaskKind,
+		#[serde(skip, default = "Instant::now")]
+		produced_at: Instant,
+	},
+	/// Header received at the already finalized height doesn't match the finalized block.
+	/// Connected node follows a conflicting chain, and the header is ignored.
+	ConflictingFinalizedHeader {
+		number: u32,
+		hash: H256,
+		finalized_hash: H256,
+		#[serde(skip, default = "Instant::now")]
+		produced_at: Instant,
+	},
+	/// Justification of the finalized block is verified against the current validator set.
+	JustificationVerified {
+		number: u32,
+		set_id: u64,
+		#[serde(skip, default = "Instant::now")]
+		produced_at: Instant,
+	},
+	/// Justification has failed verification, and the block is not finalized by it.
+	JustificationRejected {
+		number: u32,
+		reason: JustificationRejection,
+		#[serde(skip, default = "Instant::now")]
+		produced_at: Instant,
+	},
+}
+
+impl Event {
+	/// Sets the time when the event was produced
+	pub(crate) fn set_produced_at(&mut self, at: Instant) {
+		match self {
+			Event::HeaderUpdate { produced_at, .. }
+			| Event::FinalityLagging { produced_at, .. }
+			| Event::FinalityRecovered { produced_at }
+			| Event::ConsumerLagging { produced_at, .. }
+			| Event::SyncStateChanged { produced_at, .. }
+			| Event::NewFinalizedRange { produced_at, .. }
+			| Event::NoServerPeers { produced_at, .. }
+			| Event::ServerPeersRecovered { produced_at }
+			| Event::TaskCrashed { produced_at, .. }
+			| Event::ConflictingFinalizedHeader { produced_at, .. }
+			| Event::JustificationVerified { produced_at, .. }
+			| Event::JustificationRejected { produced_at, .. } => *produced_at = at,
+		}
+	}
+
+	/// Returns the time when the header was received, in case of the header update
+	pub fn received_at(&self) -> Option<Instant> {
+		match self {
+			Event::HeaderUpdate { received_at, .. } => Some(*received_at),
+			_ => None,
+		}
+	}
+
+	/// Sets the time when the header was received, in case of the header update
+	pub(crate) fn set_received_at(&mut self, at: Instant) {
+		if let Event::HeaderUpdate { received_at, .. } = self {
+			*received_at = at;
+		}
+	}
+
+	/// Returns the time when the event was produced
+	pub fn produced_at(&self) -> Instant {
+		match self {
+			Event::HeaderUpdate { produced_at, .. }
+			| Event::FinalityLagging { produced_at, .. }
+			| Event::FinalityRecovered { produced_at }
+			| Event::ConsumerLagging { produced_at, .. }
+			| Event::SyncStateChanged { produced_at, .. }
+			| Event::NewFinalizedRange { produced_at, .. }
+			| Event::NoServerPeers { produced_at, .. }
+			| Event::ServerPeersRecovered { produced_at }
+			| Event::TaskCrashed { produced_at, .. }
+			| Event::ConflictingFinalizedHeader { produced_at, .. }
+			| Event::JustificationVerified { produced_at, .. }
+			| Event::JustificationRejected { produced_at, .. } => *produced_at,
+		}
+	}
 }
 
+/// Capacity of the event channel
+pub const EVENT_CHANNEL_CAPACITY: usize = 1000;
+
+/// Interval in which the lag between the best block and the verified finalized header is checked
+const FINALITY_LAG_CHECK_INTERVAL: Duration = Duration::from_secs(20);
+
+/// Number of the latest verified finalized block hashes, against which stale headers are checked
+const FINALIZED_HASHES_CAPACITY: usize = 256;
+
+/// Maximum number of headers and justifications kept until finalized, e.g. while the head is pinned
+const MAX_UNVERIFIED_HEADERS: usize = 256;
+
 struct BlockData {
 	justifications: Vec<GrandpaJustification>,
 	unverified_headers: Vec<(Header, Instant, ValidatorSet)>,
 	current_valset: ValidatorSet,
 	next_valset: Option<ValidatorSet>,
 	last_finalized_block_header: Option<Header>,
+	// hashes of the blocks with verified finality, by block number
+	finalized_hashes: LruMap<u32, H256>,
+}
+
+/// Handle for pinning the head, which makes the finalized headers follow the fork of the pinned block.
+/// Headers beyond the pinned block, or off its fork, are kept while pinned, and output once the head is unpinned.
+#[derive(Clone, Default)]
+pub struct HeadPin {
+	pinned: Arc<Mutex<Option<PinnedFork>>>,
+	unpinned: Arc<Notify>,
+}
+
+struct PinnedFork {
+	hash: H256,
+	number: u32,
+	// hashes of the pinned block and its ancestors, by block number
+	fork: HashMap<u32, H256>,
+}
+
+impl HeadPin {
+	/// Pins the head to the block with given hash and number.
+	/// Ancestors are hashes of the pinned block ancestors which are not finalized yet, by block number.
+	pub fn pin(&self, hash: H256, number: u32, ancestors: HashMap<u32, H256>) {
+		info!("Pinning head to block {number} ({hash:?})");
+		let mut fork = ancestors;
+		fork.insert(number, hash);
+		*self.pinned.lock().unwrap() = Some(PinnedFork { hash, number, fork });
+	}
+
+	/// Removes the pin, allowing the head to advance
+	pub fn unpin(&self) {
+		if let Some((hash, number)) = self.pinned.lock().unwrap().take() {
+			info!("Unpinning head from block {number} ({hash:?})");
+			self.unpinned.notify_one();
+		}
+	}
+
+	/// Returns pinned block hash and number, if any
+	pub fn pinned(&self) -> Option<(H256, u32)> {
+		let pinned = self.pinned.lock().unwrap();
+		pinned.as_ref().map(|pinned| (pinned.hash, pinned.number))
+	}
+
+	/// Checks if the block is beyond the pinned one, or is not on the pinned fork
+	fn holds(&self, hash: H256, number: u32) -> bool {
+		let pinned = self.pinned.lock().unwrap();
+		pinned.as_ref().is_some_and(|pinned| {
+			number > pinned.number
+				|| pinned
+					.fork
+					.get(&number)
+					.is_some_and(|fork_hash| *fork_hash != hash)
+		})
+	}
+}
+
+/// Node queries made while pinning the head
+#[async_trait]
+pub trait HeadPinClient {
+	async fn get_header_by_hash(&self, block_hash: H256) -> Result<Header>;
+}
+
+#[async_trait]
+impl HeadPinClient for rpc::Client {
+	async fn get_header_by_hash(&self, block_hash: H256) -> Result<Header> {
+		rpc::Client::get_header_by_hash(self, block_hash).await
+	}
+}
+
+/// Node queries made while processing the subscription items
+#[async_trait]
+#[automock]
+pub trait Client {
+	fn cache_finalized_block_hash(&self, block_number: u32, hash: H256);
+	async fn get_header_by_block_number(&self, block_number: u32) -> Result<Header>;
+	async fn get_best_block_number(&self) -> Result<u32>;
 }
 
-pub struct SubscriptionLoop<T: Database> {
-	rpc_client: Client,
+#[async_trait]
+impl Client for rpc::Client {
+	fn cache_finalized_block_hash(&self, block_number: u32, hash: H256) {
+		rpc::Client::cache_finalized_block_hash(self, block_number, hash)
+	}
+
+	async fn get_header_by_block_number(&self, block_number: u32) -> Result<Header> {
+		rpc::Client::get_header_by_block_number(self, block_number)
+			.await
+			.map(|(header, _)| header)
+	}
+
+	async fn get_best_block_number(&self) -> Result<u32> {
+		rpc::Client::get_best_header(self)
+			.await
+			.map(|header| header.number)
+	}
+}
+
+/// Rolling window of header arrival times, used to estimate the average block time
+struct BlockTimes {
+	window: usize,
+	received_at: VecDeque<Instant>,
+}
+
+impl BlockTimes {
+	fn new(window: usize) -> Self {
+		BlockTimes {
+			window,
+			received_at: VecDeque::with_capacity(window + 1),
+		}
+	}
+
+	/// Records header arrival time and returns average interval,
+	/// once there are enough samples to fill the window.
+	fn record(&mut self, received_at: Instant) -> Option<Duration> {
+		self.received_at.push_back(received_at);
+		if self.received_at.len() > self.window + 1 {
+			self.received_at.pop_front();
+		}
+		if self.window == 0 || self.received_at.len() <= self.window {
+			return None;
+		}
+		let first = self.received_at.front()?;
+		let last = self.received_at.back()?;
+		Some(last.duration_since(*first) / self.window as u32)
+	}
+}
+
+pub struct SubscriptionLoop<T: Database, C: Client = rpc::Client> {
+	rpc_client: C,
 	event_sender: Sender<Event>,
 	state: Arc<Mutex<State>>,
 	db: T,
 	block_data: BlockData,
+	finality_lag_threshold: Option<u32>,
+	is_finality_lagging: bool,
+	is_consumer_lagging: bool,
+	block_times: BlockTimes,
+	head_pin: HeadPin,
+	sync_state: SyncState,
 }
 
 impl<T: Database> SubscriptionLoop<T> {
 	pub async fn new(
 		state: Arc<Mutex<State>>,
 		db: T,
-		rpc_client: Client,
+		rpc_client: rpc::Client,
 		event_sender: Sender<Event>,
+		config: SubscriptionsConfig,
 	) -> Result<Self> {
 		// get the Hash of the Finalized Head [with Retries]
 		let last_finalized_block_hash = rpc_client.get_finalized_head_hash().await?;
@@ -85,32 +371,197 @@ impl<T: Database> SubscriptionLoop<T> {
 				},
 				next_valset: None,
 				last_finalized_block_header: Some(last_finalized_block_header),
+				finalized_hashes: LruMap::new(FINALIZED_HASHES_CAPACITY),
 			},
+			finality_lag_threshold: config.finality_lag_threshold,
+			is_finality_lagging: false,
+			is_consumer_lagging: false,
+			block_times: BlockTimes::new(config.block_time_window),
+			head_pin: Default::default(),
+			sync_state: SyncState::Idle,
 		})
 	}
 
+	/// Returns handle for pinning the head to a specific block
+	pub fn head_pin(&self) -> HeadPin {
+		self.head_pin.clone()
+	}
+
 	pub async fn run(mut self) -> Result<()> {
 		// create subscriptions stream
 		let subscriptions = self.rpc_client.clone().subscription_stream().await;
 		futures::pin_mut!(subscriptions);
 
-		while let Some(result) = subscriptions.next().await {
-			match result {
-				Ok(sub) => {
-					self.handle_new_subscription(sub).await;
+		let mut finality_lag_interval = tokio::time::interval(FINALITY_LAG_CHECK_INTERVAL);
+
+		loop {
+			tokio::select! {
+				result = subscriptions.next() => match result {
+					Some(Ok(sub)) => {
+						self.handle_new_subscription(sub).await;
+					},
+					Some(Err(err)) => return Err(eyre!(err)),
+					None => return Ok(()),
+				},
+				_ = finality_lag_interval.tick(), if self.finality_lag_threshold.is_some() => {
+					self.check_finality_lag().await;
+				},
+				_ = self.head_pin.unpinned.notified() => {
+					// output headers held while pinned
+					self.verify_and_output_block_headers().await;
 				},
-				Err(err) => return Err(eyre!(err)),
-			};
+			}
+		}
+	}
+}
+
+impl<T: Database, C: Client> SubscriptionLoop<T, C> {
+	/// Compares the best block of the node with the last verified finalized one,
+	/// and emits an event when lag crosses the configured threshold in either direction.
+	async fn check_finality_lag(&mut self) {
+		let Some(threshold) = self.finality_lag_threshold else {
+			return;
+		};
+		let Some(finalized) = self
+			.block_data
+			.last_finalized_block_header
+			.as_ref()
+			.map(|header| header.number)
+		else {
+			return;
+		};
+
+		let best = match self.rpc_client.get_best_block_number().await {
+			Ok(best) => best,
+			Err(error) => {
+				warn!("Cannot get the best block number: {error:#}");
+				return;
+			},
+		};
+		let lag = u64::from(best.saturating_sub(finalized));
+
+		let event = match (lag > u64::from(threshold), self.is_finality_lagging) {
+			(true, false) => {
+				warn!(lag, threshold, "Finality is lagging behind the best block");
+				Event::FinalityLagging {
+					lag,
+					produced_at: Instant::now(),
+				}
+			},
+			(false, true) => {
+				info!(lag, threshold, "Finality lag recovered");
+				Event::FinalityRecovered {
+					produced_at: Instant::now(),
+				}
+			},
+			_ => return,
+		};
+
+		self.is_finality_lagging = !self.is_finality_lagging;
+		if let Err(error) = self.event_sender.send(event) {
+			error!("Cannot send finality lag event: {error}");
 		}
+	}
 
-		Ok(())
+	/// Emits an event once the event channel fills above the high watermark (80% of capacity).
+	/// Next event is emitted only after the channel drains below the half of its capacity.
+	fn check_consumer_lag(&mut self) {
+		let queued = self.event_sender.len();
+		let capacity = EVENT_CHANNEL_CAPACITY;
+		if self.is_consumer_lagging {
+			self.is_consumer_lagging = queued > capacity / 2;
+			return;
+		}
+		if queued < capacity * 8 / 10 {
+			return;
+		}
+
+		warn!(queued, capacity, "Event consumers are lagging behind");
+		self.is_consumer_lagging = true;
+		let event = Event::ConsumerLagging {
+			queued,
+			capacity,
+			produced_at: Instant::now(),
+		};
+		if let Err(error) = self.event_sender.send(event) {
+			error!("Cannot send consumer lagging event: {error}");
+		}
+	}
+
+	/// Emits an event if the sync phase has changed since the last received header
+	fn update_sync_state(&mut self, sync_state: SyncState) {
+		let from = std::mem::replace(&mut self.sync_state, sync_state);
+		if from.is_same_phase(&sync_state) {
+			return;
+		}
+
+		info!("Sync state changed from {from:?} to {sync_state:?}");
+		let event = Event::SyncStateChanged {
+			from,
+			to: sync_state,
+			produced_at: Instant::now(),
+		};
+		if let Err(error) = self.event_sender.send(event) {
+			error!("Cannot send sync state changed event: {error}");
+		}
+	}
+
+	/// Checks if the header is at or below the last finalized height, e.g. when the node is changed.
+	/// Emits an event if the header doesn't match the verified finalized block at the same height.
+	fn is_stale_header(&mut self, number: u32, hash: H256) -> bool {
+		let Some(finalized) = self.block_data.last_finalized_block_header.as_ref() else {
+			return false;
+		};
+		if number > finalized.number {
+			return false;
+		}
+
+		let finalized_hash = if number == finalized.number {
+			Some(Encode::using_encoded(finalized, blake2_256).into())
+		} else {
+			self.block_data.finalized_hashes.get(&number)
+		};
+		match finalized_hash {
+			Some(finalized_hash) if finalized_hash != hash => {
+				error!(
+					number,
+					?hash,
+					?finalized_hash,
+					"Received header conflicts with the finalized block"
+				);
+				let event = Event::ConflictingFinalizedHeader {
+					number,
+					hash,
+					finalized_hash,
+					produced_at: Instant::now(),
+				};
+				if let Err(error) = self.event_sender.send(event) {
+					error!("Cannot send conflicting finalized header event: {error}");
+				}
+			},
+			_ => debug!(number, "Ignoring header at the already finalized height"),
+		}
+		true
 	}
 
 	async fn handle_new_subscription(&mut self, subscription: Subscription) {
+		self.check_consumer_lag();
 		match subscription {
 			Subscription::Header(header) => {
 				let received_at = Instant::now();
-				self.state.lock().unwrap().latest = header.clone().number;
+				let hash: H256 = Encode::using_encoded(&header, blake2_256).into();
+				if self.is_stale_header(header.number, hash) {
+					return;
+				}
+				let average_block_time = self.block_times.record(received_at);
+				let sync_state = {
+					let mut state = self.state.lock().unwrap();
+					state.latest = header.number;
+					state.average_block_time = average_block_time;
+					state.latest_received_at = Some(SystemTime::now());
+					state.sync_state()
+				};
+				self.update_sync_state(sync_state);
 				info!("Header no.: {}", header.number);
 
 				// if new validator set becomes active, replace the current one
@@ -124,6 +575,21 @@ impl<T: Database> SubscriptionLoop<T> {
 					received_at,
 					self.block_data.current_valset.clone(),
 				));
+				// drop the oldest header if too many are kept (e.g. while pinned),
+				// since skipped headers are fetched from RPC once finalized
+				if self.block_data.unverified_headers.len() > MAX_UNVERIFIED_HEADERS {
+					if let Some(pos) = self
+						.block_data
+						.unverified_headers
+						.iter()
+						.enumerate()
+						.min_by_key(|(_, (h, _, _))| h.number)
+						.map(|(pos, _)| pos)
+					{
+						let (header, _, _) = self.block_data.unverified_headers.swap_remove(pos);
+						debug!("Dropping unverified header {}", header.number);
+					}
+				}
 
 				// search the header logs for validator set change
 				let mut new_auths = filter_auth_set_changes(&header);
@@ -149,11 +615,30 @@ impl<T: Database> SubscriptionLoop<T> {
 				}
 			},
 			Subscription::Justification(justification) => {
+				let is_stale = self
+					.block_data
+					.last_finalized_block_header
+					.as_ref()
+					.is_some_and(|finalized| {
+						justification.commit.target_number <= finalized.number
+					});
+				if is_stale {
+					debug!(
+						"Ignoring justification at the already finalized block no.: {}",
+						justification.commit.target_number
+					);
+					return;
+				}
 				info!(
 					"New justification at block no.: {}, hash: {:?}",
 					justification.commit.target_number, justification.commit.target_hash
 				);
 				self.block_data.justifications.push(justification);
+				// drop the oldest justification if too many are kept while pinned,
+				// since the latter one finalizes the skipped blocks too
+				if self.block_data.justifications.len() > MAX_UNVERIFIED_HEADERS {
+					self.block_data.justifications.remove(0);
+				}
 			},
 		}
 		// check headers
@@ -171,13 +656,47 @@ impl<T: Database> SubscriptionLoop<T> {
 				.map(|(h, _, _)| Encode::using_encoded(h, blake2_256).into())
 				.position(|hash| justification.commit.target_hash == hash)
 			{
+				// while pinned, do not advance beyond the pinned block
+				let number = self.block_data.unverified_headers[pos].0.number;
+				if self
+					.head_pin
+					.holds(justification.commit.target_hash, number)
+				{
+					trace!("Head is pinned, holding block {number}");
+					self.block_data.justifications.push(justification);
+					break;
+				}
+
 				// basically, pop it out of the collection
 				let (header, received_at, valset) =
 					self.block_data.unverified_headers.swap_remove(pos);
 
-				let is_final = check_finality(&valset, &justification);
-
-				is_final.expect("Finality check failed");
+				let event = match check_finality(&valset, &justification) {
+					Ok(()) => Event::JustificationVerified {
+						number,
+						set_id: valset.set_id,
+						produced_at: Instant::now(),
+					},
+					Err(reason) => {
+						error!("Justification of block {number} is rejected: {reason}");
+						Event::JustificationRejected {
+							number,
+							reason,
+							produced_at: Instant::now(),
+						}
+					},
+				};
+				let is_rejected = matches!(event, Event::JustificationRejected { .. });
+				if let Err(error) = self.event_sender.send(event) {
+					error!("Cannot send justification event: {error}");
+				}
+				if is_rejected {
+					// Header is kept, since it can still be finalized by the valid justification
+					self.block_data
+						.unverified_headers
+						.push((header, received_at, valset));
+					continue;
+				}
 
 				// To avoid locking the global state all the time, after finality is synced, it will not be necessary to read the state
 				if !finality_synced {
@@ -198,6 +717,7 @@ impl<T: Database> SubscriptionLoop<T> {
 						.unwrap();
 				}
 
+				let mut finalized_hashes: Vec<H256> = vec![];
 				// try and get get all the skipped blocks, if they exist
 				if let Some(last_header) = self.block_data.last_finalized_block_header.as_ref() {
 					for bl_num in (last_header.number + 1)..header.number {
@@ -219,16 +739,21 @@ impl<T: Database> SubscriptionLoop<T> {
 									.rpc_client
 									.get_header_by_block_number(bl_num)
 									.await
-									.unwrap()
-									.0;
+									.unwrap();
 								(a, Instant::now())
 							},
 						};
+						let hash = Encode::using_encoded(&header, blake2_256).into();
+						// skipped block is finalized by its verified descendant
+						self.rpc_client.cache_finalized_block_hash(bl_num, hash);
+						self.block_data.finalized_hashes.insert(bl_num, hash);
+						finalized_hashes.push(hash);
 						// send as output event
 						self.event_sender
 							.send(Event::HeaderUpdate {
 								header,
 								received_at,
+								produced_at: Instant::now(),
 							})
 							.unwrap();
 					}
@@ -239,17 +764,34 @@ impl<T: Database> SubscriptionLoop<T> {
 				self.block_data.last_finalized_block_header = Some(header.clone());
 
 				// finally, send the Verified Block Header
-				self.state
-					.lock()
-					.unwrap()
-					.header_verified
-					.set(header.number);
+				{
+					let mut state = self.state.lock().unwrap();
+					state.header_verified.set(header.number);
+					state.finalized_header = Some(header.clone());
+				}
+				let hash = Encode::using_encoded(&header, blake2_256).into();
+				self.rpc_client
+					.cache_finalized_block_hash(header.number, hash);
+				self.block_data.finalized_hashes.insert(header.number, hash);
+				finalized_hashes.push(hash);
+				let to = header.number;
 				self.event_sender
 					.send(Event::HeaderUpdate {
 						header,
 						received_at,
+						produced_at: Instant::now(),
 					})
 					.unwrap();
+
+				if finalized_hashes.len() > 1 {
+					// Sending fails only if there are no subscribers
+					_ = self.event_sender.send(Event::NewFinalizedRange {
+						from: to + 1 - finalized_hashes.len() as u32,
+						to,
+						hashes: finalized_hashes,
+						produced_at: Instant::now(),
+					});
+				}
 			} else {
 				trace!("Matched pair of header/justification not found.");
 				self.block_data.justifications.push(justification);
@@ -258,3 +800,446 @@ impl<T: Database> SubscriptionLoop<T> {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		BlockData, BlockTimes, Event, HeadPin, MockClient, SubscriptionLoop,
+		EVENT_CHANNEL_CAPACITY, MAX_UNVERIFIED_HEADERS,
+	};
+	use crate::{
+		data::mem_db::MemoryDB,
+		finality::ValidatorSet,
+		network::rpc::Subscription,
+		types::{
+			Commit, GrandpaJustification, Precommit, SignedPrecommit, SignerMessage, SyncState,
+		},
+	};
+	use avail_subxt::{
+		api::runtime_types::avail_core::{
+			data_lookup::compact::CompactDataLookup,
+			header::extension::{v3, HeaderExtension},
+			kate_commitment::v3::KateCommitment,
+		},
+		primitives::Header,
+		utils::H256,
+	};
+	use codec::Encode;
+	use mockall::predicate::eq;
+	use sp_core::{blake2_256, ed25519, Pair};
+	use std::{
+		collections::HashMap,
+		time::{Duration, Instant},
+	};
+	use subxt::config::substrate::Digest;
+	use tokio::sync::broadcast;
+
+	const SET_ID: u64 = 50;
+
+	fn validator() -> ed25519::Pair {
+		ed25519::Pair::from_seed(&[1u8; 32])
+	}
+
+	fn header_hash(header: &Header) -> H256 {
+		Encode::using_encoded(header, blake2_256).into()
+	}
+
+	fn headers(from: u32, count: u32) -> Vec<Header> {
+		let mut parent_hash = H256::default();
+		(from..from + count)
+			.map(|number| {
+				let header = Header {
+					parent_hash,
+					number,
+					state_root: H256::default(),
+					extrinsics_root: H256::default(),
+					extension: HeaderExtension::V3(v3::HeaderExtension {
+						commitment: KateCommitment::default(),
+						app_lookup: CompactDataLookup {
+							size: 0,
+							index: vec![],
+						},
+					}),
+					digest: Digest { logs: vec![] },
+				};
+				parent_hash = header_hash(&header);
+				header
+			})
+			.collect()
+	}
+
+	/// Justification of the header, signed by the only validator
+	fn justification(header: &Header) -> GrandpaJustification {
+		let precommit = Precommit {
+			target_hash: header_hash(header),
+			target_number: header.number,
+		};
+		let signed_message = Encode::encode(&(
+			&SignerMessage::PrecommitMessage(precommit.clone()),
+			&1u64,
+			&SET_ID,
+		));
+		GrandpaJustification {
+			round: 1,
+			commit: Commit {
+				target_hash: precommit.target_hash,
+				target_number: precommit.target_number,
+				precommits: vec![SignedPrecommit {
+					signature: validator().sign(&signed_message),
+					id: validator().public(),
+					precommit,
+				}],
+			},
+			votes_ancestries: vec![],
+		}
+	}
+
+	fn rpc_client() -> MockClient {
+		let mut rpc_client = MockClient::new();
+		rpc_client
+			.expect_cache_finalized_block_hash()
+			.return_const(());
+		rpc_client
+	}
+
+	fn subscription_loop(
+		rpc_client: MockClient,
+		finalized: &Header,
+	) -> (
+		SubscriptionLoop<MemoryDB, MockClient>,
+		broadcast::Receiver<Event>,
+	) {
+		let (event_sender, event_receiver) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+		let subscription_loop = SubscriptionLoop {
+			rpc_client,
+			event_sender,
+			state: Default::default(),
+			db: MemoryDB::default(),
+			block_data: BlockData {
+				justifications: vec![],
+				unverified_headers: vec![],
+				current_valset: ValidatorSet {
+					set_id: SET_ID,
+					validator_set: vec![validator().public()],
+				},
+				next_valset: None,
+				last_finalized_block_header: Some(finalized.clone()),
+				finalized_hashes: LruMap::new(FINALIZED_HASHES_CAPACITY),
+			},
+			finality_lag_threshold: None,
+			is_finality_lagging: false,
+			is_consumer_lagging: false,
+			block_times: BlockTimes::new(0),
+			head_pin: Default::default(),
+			sync_state: SyncState::Idle,
+		};
+		(subscription_loop, event_receiver)
+	}
+
+	/// Receives all sent events
+	fn events(receiver: &mut broadcast::Receiver<Event>) -> Vec<Event> {
+		std::iter::from_fn(|| receiver.try_recv().ok()).collect()
+	}
+
+	/// Block numbers of the sent header updates
+	fn header_updates(events: &[Event]) -> Vec<u32> {
+		events
+			.iter()
+			.filter_map(|event| match event {
+				Event::HeaderUpdate { header, .. } => Some(header.number),
+				_ => None,
+			})
+			.collect()
+	}
+
+	impl SubscriptionLoop<MemoryDB, MockClient> {
+		/// Handles the header, followed by its justification
+		async fn finalize(&mut self, header: &Header) {
+			self.handle_new_subscription(Subscription::Header(header.clone()))
+				.await;
+			self.handle_new_subscription(Subscription::Justification(justification(header)))
+				.await;
+		}
+	}
+
+	#[test]
+	fn head_pin_holds_blocks_beyond_pin() {
+		let head_pin = HeadPin::default();
+		let (pinned, other) = (H256::repeat_byte(1), H256::repeat_byte(2));
+		assert!(!head_pin.holds(other, 11));
+
+		head_pin.pin(pinned, 10, HashMap::new());
+		assert!(!head_pin.holds(other, 9));
+		assert!(!head_pin.holds(pinned, 10));
+		assert!(head_pin.holds(other, 10));
+		assert!(head_pin.holds(other, 11));
+
+		head_pin.unpin();
+		assert!(!head_pin.holds(other, 11));
+	}
+
+	#[test]
+	fn head_pin_follows_pinned_fork() {
+		let head_pin = HeadPin::default();
+		let (ancestor, pinned, other) = (
+			H256::repeat_byte(1),
+			H256::repeat_byte(2),
+			H256::repeat_byte(3),
+		);
+		head_pin.pin(pinned, 10, HashMap::from([(9, ancestor)]));
+		assert!(!head_pin.holds(other, 8));
+		assert!(!head_pin.holds(ancestor, 9));
+		assert!(head_pin.holds(other, 9));
+		assert!(!head_pin.holds(pinned, 10));
+		assert!(head_pin.holds(other, 10));
+	}
+
+	#[tokio::test]
+	async fn unpin_outputs_held_headers() {
+		let headers = headers(10, 3);
+		let (mut subscription_loop, mut receiver) = subscription_loop(rpc_client(), &headers[0]);
+		subscription_loop
+			.head_pin
+			.pin(header_hash(&headers[1]), headers[1].number, HashMap::new());
+
+		for header in &headers[1..] {
+			subscription_loop.finalize(header).await;
+		}
+		assert_eq!(header_updates(&events(&mut receiver)), vec![11]);
+
+		// Run loop is notified on unpin, and outputs held headers
+		subscription_loop.head_pin.unpin();
+		subscription_loop.head_pin.unpinned.notified().await;
+		subscription_loop.verify_and_output_block_headers().await;
+		assert_eq!(header_updates(&events(&mut receiver)), vec![12]);
+	}
+
+	#[tokio::test]
+	async fn unverified_headers_are_capped() {
+		let headers = headers(10, MAX_UNVERIFIED_HEADERS as u32 + 2);
+		let (mut subscription_loop, _receiver) = subscription_loop(rpc_client(), &headers[0]);
+		subscription_loop
+			.head_pin
+			.pin(header_hash(&headers[0]), headers[0].number, HashMap::new());
+
+		for header in &headers[1..] {
+			subscription_loop.finalize(header).await;
+		}
+		let block_data = &subscription_loop.block_data;
+		assert_eq!(block_data.unverified_headers.len(), MAX_UNVERIFIED_HEADERS);
+		assert_eq!(block_data.justifications.len(), MAX_UNVERIFIED_HEADERS);
+		// the oldest header is dropped
+		let oldest = block_data
+			.unverified_headers
+			.iter()
+			.map(|(h, _, _)| h.number)
+			.min();
+		assert_eq!(oldest, Some(headers[2].number));
+	}
+
+	#[test]
+	fn block_times_average() {
+		let mut block_times = BlockTimes::new(3);
+		let start = Instant::now();
+		let at = |secs| start + Duration::from_secs(secs);
+
+		assert_eq!(block_times.record(at(0)), None);
+		assert_eq!(block_times.record(at(20)), None);
+		assert_eq!(block_times.record(at(40)), None);
+		assert_eq!(block_times.record(at(60)), Some(Duration::from_secs(20)));
+		// Oldest sample is dropped from the window
+		assert_eq!(block_times.record(at(110)), Some(Duration::from_secs(30)));
+	}
+
+	#[tokio::test]
+	async fn stale_header_is_ignored() {
+		let chain = headers(8, 3);
+		let (mut subscription_loop, mut receiver) = subscription_loop(rpc_client(), &chain[0]);
+		for header in &chain[1..] {
+			subscription_loop.finalize(header).await;
+		}
+		events(&mut receiver);
+
+		for header in &chain[1..] {
+			subscription_loop
+				.handle_new_subscription(Subscription::Header(header.clone()))
+				.await;
+		}
+		assert!(subscription_loop.block_data.unverified_headers.is_empty());
+		assert!(events(&mut receiver).is_empty());
+	}
+
+	#[tokio::test]
+	async fn stale_justification_is_ignored() {
+		let chain = headers(10, 1);
+		let (mut subscription_loop, mut receiver) = subscription_loop(rpc_client(), &chain[0]);
+
+		subscription_loop
+			.handle_new_subscription(Subscription::Justification(justification(&chain[0])))
+			.await;
+		assert!(subscription_loop.block_data.justifications.is_empty());
+		assert!(events(&mut receiver).is_empty());
+	}
+
+	#[tokio::test]
+	async fn conflicting_finalized_header() {
+		let finalized = &headers(9, 2)[1];
+		let conflicting = &headers(10, 1)[0];
+		let (mut subscription_loop, mut receiver) = subscription_loop(rpc_client(), finalized);
+
+		subscription_loop
+			.handle_new_subscription(Subscription::Header(conflicting.clone()))
+			.await;
+		assert!(subscription_loop.block_data.unverified_headers.is_empty());
+		let events = events(&mut receiver);
+		assert_eq!(events.len(), 1);
+		assert!(matches!(
+			events[0],
+			Event::ConflictingFinalizedHeader {
+				number: 10,
+				hash,
+				finalized_hash,
+				..
+			} if hash == header_hash(conflicting) && finalized_hash == header_hash(finalized)
+		));
+	}
+
+	#[tokio::test]
+	async fn finality_lag_is_measured_from_best_block() {
+		let headers = headers(10, 1);
+		let mut rpc_client = MockClient::new();
+		let mut best = mockall::Sequence::new();
+		for number in [25, 30, 15] {
+			rpc_client
+				.expect_get_best_block_number()
+				.times(1)
+				.in_sequence(&mut best)
+				.returning(move || Ok(number));
+		}
+		let (mut subscription_loop, mut receiver) = subscription_loop(rpc_client, &headers[0]);
+		subscription_loop.finality_lag_threshold = Some(10);
+
+		subscription_loop.check_finality_lag().await;
+		let lagging = events(&mut receiver);
+		assert!(matches!(
+			lagging[..],
+			[Event::FinalityLagging { lag: 15, .. }]
+		));
+
+		// Event is emitted only when the threshold is crossed
+		subscription_loop.check_finality_lag().await;
+		assert!(events(&mut receiver).is_empty());
+
+		subscription_loop.check_finality_lag().await;
+		let recovered = events(&mut receiver);
+		assert!(matches!(recovered[..], [Event::FinalityRecovered { .. }]));
+	}
+
+	#[test]
+	fn consumer_lag_watermarks() {
+		let (mut subscription_loop, mut receiver) =
+			subscription_loop(rpc_client(), &headers(1, 1)[0]);
+		let send = |subscription_loop: &SubscriptionLoop<MemoryDB, MockClient>, count| {
+			for _ in 0..count {
+				let event = Event::FinalityRecovered {
+					produced_at: Instant::now(),
+				};
+				subscription_loop.event_sender.send(event).unwrap();
+			}
+		};
+		let is_consumer_lagging = |event: Event| {
+			matches!(
+				event,
+				Event::ConsumerLagging {
+					capacity: EVENT_CHANNEL_CAPACITY,
+					..
+				}
+			)
+		};
+
+		// Event is emitted once queued events reach the high watermark
+		send(&subscription_loop, EVENT_CHANNEL_CAPACITY * 8 / 10 - 1);
+		subscription_loop.check_consumer_lag();
+		assert!(!subscription_loop.is_consumer_lagging);
+		send(&subscription_loop, 1);
+		subscription_loop.check_consumer_lag();
+		assert!(subscription_loop.is_consumer_lagging);
+		let lagging = events(&mut receiver);
+		assert!(is_consumer_lagging(lagging.last().unwrap().clone()));
+
+		// Next event is emitted only after draining below the half of the capacity
+		send(&subscription_loop, EVENT_CHANNEL_CAPACITY * 8 / 10);
+		subscription_loop.check_consumer_lag();
+		assert!(subscription_loop.is_consumer_lagging);
+		events(&mut receiver);
+		subscription_loop.check_consumer_lag();
+		assert!(!subscription_loop.is_consumer_lagging);
+		send(&subscription_loop, EVENT_CHANNEL_CAPACITY * 8 / 10);
+		subscription_loop.check_consumer_lag();
+		assert!(is_consumer_lagging(events(&mut receiver).pop().unwrap()));
+	}
+
+	#[tokio::test]
+	async fn new_finalized_range() {
+		let chain = headers(10, 4);
+		let mut rpc_client = MockClient::new();
+		for header in &chain[1..] {
+			rpc_client
+				.expect_cache_finalized_block_hash()
+				.with(eq(header.number), eq(header_hash(header)))
+				.times(1)
+				.return_const(());
+		}
+		// Skipped header which wasn't received is fetched from the node
+		let skipped = chain[1].clone();
+		rpc_client
+			.expect_get_header_by_block_number()
+			.with(eq(11))
+			.returning(move |_| Ok(skipped.clone()));
+		let (mut subscription_loop, mut receiver) = subscription_loop(rpc_client, &chain[0]);
+
+		for header in &chain[2..] {
+			subscription_loop
+				.handle_new_subscription(Subscription::Header(header.clone()))
+				.await;
+		}
+		subscription_loop
+			.handle_new_subscription(Subscription::Justification(justification(&chain[3])))
+			.await;
+
+		let events = events(&mut receiver);
+		assert_eq!(header_updates(&events), vec![11, 12, 13]);
+		let expected_hashes = chain[1..].iter().map(header_hash).collect::<Vec<_>>();
+		assert!(matches!(
+			events.last(),
+			Some(Event::NewFinalizedRange {
+				from: 11,
+				to: 13,
+				hashes,
+				..
+			}) if *hashes == expected_hashes
+		));
+		assert!(subscription_loop.block_data.unverified_headers.is_empty());
+	}
+
+	#[tokio::test]
+	async fn hash_is_cached_once_verified() {
+		let chain = headers(10, 2);
+		// Caching the unverified header would fail, since no call is expected yet
+		let (mut subscription_loop, _receiver) = subscription_loop(MockClient::new(), &chain[0]);
+		subscription_loop
+			.handle_new_subscription(Subscription::Header(chain[1].clone()))
+			.await;
+
+		subscription_loop
+			.rpc_client
+			.expect_cache_finalized_block_hash()
+			.with(eq(11), eq(header_hash(&chain[1])))
+			.times(1)
+			.return_const(());
+		subscription_loop
+			.handle_new_subscription(Subscription::Justification(justification(&chain[1])))
+			.await;
+		subscription_loop.rpc_client.checkpoint();
+	}
+}
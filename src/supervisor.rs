@@ -0,0 +1,277 @@
+//! Supervision of the light client tasks.
+//!
+//! Panicked tasks are reported with [`Event::TaskCrashed`]. Restartable tasks are started again with fresh state,
+//! while the tasks holding resources which cannot be recreated (e.g. the network event loop) trigger the shutdown.
+//! Global panic hook leaves the panics of supervised tasks to the supervisor, and triggers the shutdown on any other panic.
+
+use color_eyre::Result;
+use futures::future::BoxFuture;
+use std::{
+	future::Future,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+use tokio::{sync::broadcast, task::JoinHandle, time::sleep};
+use tracing::{error, warn};
+
+use crate::{logging::TaskKind, network::rpc::Event, shutdown::Controller};
+
+/// Delay before the crashed task is restarted, to avoid restarting it in a tight loop
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+tokio::task_local! {
+	/// Kind of the supervised task, set while its future is polled
+	static SUPERVISED_TASK: TaskKind;
+}
+
+/// Returns the kind of the supervised task which is currently running, if any
+pub fn current_task() -> Option<TaskKind> {
+	SUPERVISED_TASK.try_with(|task| *task).ok()
+}
+
+/// Installs color-eyre hooks, and the global panic hook which reports the panic.
+/// Panic outside of the supervised tasks triggers the shutdown, while the supervisor decides
+/// whether the crashed supervised task is restarted or the shutdown is triggered.
+pub fn install_panic_hooks(shutdown: Controller<String>) -> Result<()> {
+	// initialize color-eyre hooks
+	let (panic_hook, eyre_hook) = color_eyre::config::HookBuilder::default()
+		.display_location_section(true)
+		.display_env_section(true)
+		.into_hooks();
+
+	// install hook as global handler
+	eyre_hook.install()?;
+
+	std::panic::set_hook(Box::new(move |panic_info| {
+		// trigger shutdown to stop other tasks if panic occurs outside of the supervised tasks
+		if current_task().is_none() {
+			let _ = shutdown.trigger_shutdown("Panic occurred, shuting down".to_string());
+		}
+
+		let msg = format!("{}", panic_hook.panic_report(panic_info));
+		error!("Error: {}", strip_ansi_escapes::strip_str(msg));
+
+		#[cfg(debug_assertions)]
+		{
+			// better-panic stacktrace that is only enabled when debugging
+			better_panic::Settings::auto()
+				.most_recent_first(false)
+				.lineno_suffix(true)
+				.verbosity(better_panic::Verbosity::Medium)
+				.create_panic_handler()(panic_info);
+		}
+	}));
+	Ok(())
+}
+
+/// Function which wraps the task future before it is spawned, e.g. to instrument it
+pub type SpawnHook =
+	dyn Fn(TaskKind, BoxFuture<'static, ()>) -> BoxFuture<'static, ()> + Send + Sync;
+
+#[derive(Clone)]
+pub struct Supervisor {
+	events: broadcast::Sender<Event>,
+	shutdown: Controller<String>,
+	spawn_hook: Option<Arc<SpawnHook>>,
+}
+
+impl Supervisor {
+	pub fn new(events: broadcast::Sender<Event>, shutdown: Controller<String>) -> Self {
+		Supervisor {
+			events,
+			shutdown,
+			spawn_hook: None,
+		}
+	}
+
+	/// Sets the hook which wraps each task future (including restarted ones) before it is spawned
+	pub fn with_spawn_hook(
+		mut self,
+		spawn_hook: impl Fn(TaskKind, BoxFuture<'static, ()>) -> BoxFuture<'static, ()>
+			+ Send
+			+ Sync
+			+ 'static,
+	) -> Self {
+		self.spawn_hook = Some(Arc::new(spawn_hook));
+		self
+	}
+
+	/// Spawns the task, and triggers the shutdown if it panics.
+	pub fn spawn(
+		&self,
+		task: TaskKind,
+		future: impl Future<Output = ()> + Send + 'static,
+	) -> JoinHandle<()> {
+		let mut future = Some(future);
+		tokio::spawn(self.clone().run(task, false, move || {
+			future.take().expect("Task is started only once")
+		}))
+	}
+
+	/// Spawns the task created by `new_task`, and creates it again if it panics.
+	/// Task is not restarted if it completes or the shutdown is triggered.
+	pub fn spawn_restartable<F>(
+		&self,
+		task: TaskKind,
+		new_task: impl FnMut() -> F + Send + 'static,
+	) -> JoinHandle<()>
+	where
+		F: Future<Output = ()> + Send + 'static,
+	{
+		tokio::spawn(self.clone().run(task, true, new_task))
+	}
+
+	async fn run<F>(self, task: TaskKind, restart: bool, mut new_task: impl FnMut() -> F)
+	where
+		F: Future<Output = ()> + Send + 'static,
+	{
+		loop {
+			let mut future: BoxFuture<'static, ()> = Box::pin(new_task());
+			if let Some(spawn_hook) = &self.spawn_hook {
+				future = spawn_hook(task, future);
+			}
+			let future = SUPERVISED_TASK.scope(task, future);
+			let Err(error) = tokio::spawn(self.shutdown.with_cancel(future)).await else {
+				return;
+			};
+			if !error.is_panic() {
+				return;
+			}
+
+			error!("Task {task} has crashed: {error}");
+			// Sending fails only if there are no subscribers
+			_ = self.events.send(Event::TaskCrashed {
+				task,
+				produced_at: Instant::now(),
+			});
+
+			if !restart {
+				let _ = self
+					.shutdown
+					.trigger_shutdown(format!("Task {task} has crashed"));
+				return;
+			}
+			warn!("Restarting task {task} in {RESTART_DELAY:?}");
+			if self
+				.shutdown
+				.with_cancel(sleep(RESTART_DELAY))
+				.await
+				.is_err()
+			{
+				return;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{current_task, install_panic_hooks, Supervisor};
+	use crate::{logging::TaskKind, network::rpc::Event, shutdown::Controller};
+	use futures::FutureExt;
+	use std::sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	};
+	use tokio::sync::broadcast;
+
+	#[tokio::test]
+	async fn restart_crashed_task() {
+		let (sender, mut receiver) = broadcast::channel(10);
+		let starts = Arc::new(AtomicUsize::new(0));
+		let task_starts = starts.clone();
+		Supervisor::new(sender, Controller::new())
+			.spawn_restartable(TaskKind::SyncClient, move || {
+				let starts = task_starts.fetch_add(1, Ordering::SeqCst);
+				async move {
+					if starts == 0 {
+						panic!("Task has crashed");
+					}
+				}
+			})
+			.await
+			.unwrap();
+
+		assert_eq!(starts.load(Ordering::SeqCst), 2);
+		assert!(matches!(
+			receiver.try_recv(),
+			Ok(Event::TaskCrashed {
+				task: TaskKind::SyncClient,
+				..
+			})
+		));
+		assert!(receiver.try_recv().is_err());
+	}
+
+	#[tokio::test]
+	async fn panic_hook_leaves_crashes_to_supervisor() {
+		let (sender, _) = broadcast::channel(10);
+		let shutdown = Controller::new();
+		install_panic_hooks(shutdown.clone()).unwrap();
+
+		let starts = Arc::new(AtomicUsize::new(0));
+		let task_starts = starts.clone();
+		Supervisor::new(sender, shutdown.clone())
+			.spawn_restartable(TaskKind::LightClient, move || {
+				let starts = task_starts.fetch_add(1, Ordering::SeqCst);
+				async move {
+					assert_eq!(current_task(), Some(TaskKind::LightClient));
+					if starts == 0 {
+						panic!("Task has crashed");
+					}
+				}
+			})
+			.await
+			.unwrap();
+		assert_eq!(starts.load(Ordering::SeqCst), 2);
+		assert!(!shutdown.is_shutdown_triggered());
+
+		// Panic outside of the supervised tasks triggers the shutdown
+		assert!(std::panic::catch_unwind(|| panic!("Unsupervised panic")).is_err());
+		_ = std::panic::take_hook();
+		assert!(shutdown.is_shutdown_triggered());
+	}
+
+	#[tokio::test]
+	async fn shutdown_on_crash() {
+		let (sender, mut receiver) = broadcast::channel(10);
+		let shutdown = Controller::new();
+		Supervisor::new(sender, shutdown.clone())
+			.spawn(TaskKind::Network, async {
+				panic!("Task has crashed");
+			})
+			.await
+			.unwrap();
+
+		assert!(shutdown.is_shutdown_triggered());
+		assert!(matches!(
+			receiver.try_recv(),
+			Ok(Event::TaskCrashed {
+				task: TaskKind::Network,
+				..
+			})
+		));
+	}
+
+	#[tokio::test]
+	async fn spawn_hook_wraps_tasks() {
+		let (sender, _) = broadcast::channel(10);
+		let wrapped = Arc::new(AtomicUsize::new(0));
+		let hook_wrapped = wrapped.clone();
+		Supervisor::new(sender, Controller::new())
+			.with_spawn_hook(move |task, future| {
+				assert_eq!(task, TaskKind::Maintenance);
+				let wrapped = hook_wrapped.clone();
+				future
+					.map(move |_| {
+						wrapped.fetch_add(1, Ordering::SeqCst);
+					})
+					.boxed()
+			})
+			.spawn(TaskKind::Maintenance, async {})
+			.await
+			.unwrap();
+
+		assert_eq!(wrapped.load(Ordering::SeqCst), 1);
+	}
+}
@@ -16,7 +16,7 @@
 //! In case RPC is disabled, RPC calls will be skipped.
 
 use crate::{
-	data::{Database, Key},
+	data::{store_block_header, Database, Key},
 	network::{
 		self,
 		rpc::{self, Client as RpcClient},
@@ -88,8 +88,7 @@ impl<T: Database + Sync> Client for SyncClient<T> {
 			Err(error) => return Err(error),
 		};
 
-		self.db
-			.put(Key::BlockHeader(block_number), header.clone())
+		store_block_header(&self.db, block_number, &header)
 			.wrap_err("Sync Client failed to store Block Header")?;
 
 		Ok((header, hash))
@@ -6,6 +6,7 @@ use color_eyre::{
 	Result,
 };
 use futures::future::join_all;
+use mockall::automock;
 use sp_core::{
 	blake2_256,
 	ed25519::{self},
@@ -16,10 +17,10 @@ use std::{
 	sync::{Arc, Mutex},
 };
 use subxt::{storage::StorageKey, utils::AccountId32};
-use tracing::{error, info, trace};
+use tracing::{error, info, trace, warn};
 
 use crate::{
-	data::{Database, FinalitySyncCheckpoint, Key},
+	data::{self, Database, FinalitySyncCheckpoint, Key},
 	finality::{check_finality, ValidatorSet},
 	network::rpc::{self, WrappedProof},
 	shutdown::Controller,
@@ -28,6 +29,7 @@ use crate::{
 };
 
 #[async_trait]
+#[automock]
 pub trait Client {
 	fn store_block_header(&self, block_number: u32, header: Header) -> Result<()>;
 	fn get_checkpoint(&self) -> Result<Option<FinalitySyncCheckpoint>>;
@@ -134,8 +136,7 @@ impl<T: Database + Sync> Client for SyncFinality<T> {
 	}
 
 	fn store_block_header(&self, block_number: u32, header: Header) -> Result<()> {
-		self.db
-			.put(Key::BlockHeader(block_number), header)
+		data::store_block_header(&self.db, block_number, &header)
 			.wrap_err("Finality Sync Client failed to store Block Header")
 	}
 
@@ -217,8 +218,9 @@ pub async fn run(
 	shutdown: Controller<String>,
 	state: Arc<Mutex<State>>,
 	from_header: Header,
+	trusted_checkpoint: Option<FinalitySyncCheckpoint>,
 ) {
-	if let Err(error) = sync(client, state, from_header).await {
+	if let Err(error) = sync(client, state, from_header, trusted_checkpoint).await {
 		error!("Cannot sync finality {error}");
 		let _ = shutdown.trigger_shutdown(format!("Cannot sync finality {error:#}"));
 	};
@@ -228,10 +230,52 @@ pub async fn sync(
 	client: impl Client,
 	state: Arc<Mutex<State>>,
 	mut from_header: Header,
+	trusted_checkpoint: Option<FinalitySyncCheckpoint>,
 ) -> Result<()> {
 	let gen_hash = client.get_genesis_hash().await?;
 
-	let checkpoint = client.get_checkpoint()?;
+	// Checkpoint which cannot be read, or is ahead of the finalized header, is not consistent
+	// (e.g. after crash or switching networks), so finality is synced from genesis instead
+	let checkpoint = match client.get_checkpoint() {
+		Ok(Some(checkpoint)) if checkpoint.number > from_header.number => {
+			warn!(
+				"Checkpoint at block {} is ahead of the finalized block {}, discarding it",
+				checkpoint.number, from_header.number
+			);
+			None
+		},
+		Ok(checkpoint) => checkpoint,
+		Err(error) => {
+			warn!("Checkpoint cannot be read, discarding it: {error:#}");
+			None
+		},
+	};
+	// Trusted authority set ahead of the finalized header is misconfigured (e.g. set of another network),
+	// so sync fails instead of silently starting from the checkpoint or genesis
+	if let Some(trusted) = trusted_checkpoint
+		.as_ref()
+		.filter(|trusted| trusted.number > from_header.number)
+	{
+		return Err(eyre!(
+			"Trusted authority set at block {} is ahead of the finalized block {}",
+			trusted.number,
+			from_header.number
+		));
+	}
+	// Trusted authority set is used if it is ahead of the stored checkpoint
+	let checkpoint = match (checkpoint, trusted_checkpoint) {
+		(Some(checkpoint), Some(trusted)) if trusted.number <= checkpoint.number => {
+			Some(checkpoint)
+		},
+		(_, Some(trusted)) => {
+			info!(
+				"Using trusted authority set {} at block {}",
+				trusted.set_id, trusted.number
+			);
+			Some(trusted)
+		},
+		(checkpoint, None) => checkpoint,
+	};
 
 	info!("Starting finality validation sync.");
 	let mut set_id: u64;
@@ -328,3 +372,99 @@ pub async fn sync(
 	info!("Finality is fully synced.");
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{sync, FinalitySyncCheckpoint, MockClient};
+	use crate::types::State;
+	use avail_subxt::{
+		api::runtime_types::avail_core::{
+			data_lookup::compact::CompactDataLookup,
+			header::extension::{v3, HeaderExtension},
+			kate_commitment::v3::KateCommitment,
+		},
+		primitives::Header,
+	};
+	use mockall::predicate::{always, eq};
+	use sp_core::H256;
+	use std::sync::{Arc, Mutex};
+	use subxt::config::substrate::Digest;
+
+	fn header(number: u32, parent_hash: H256) -> Header {
+		Header {
+			parent_hash,
+			number,
+			state_root: H256::default(),
+			extrinsics_root: H256::default(),
+			extension: HeaderExtension::V3(v3::HeaderExtension {
+				commitment: KateCommitment::default(),
+				app_lookup: CompactDataLookup {
+					size: 0,
+					index: vec![],
+				},
+			}),
+			digest: Digest { logs: vec![] },
+		}
+	}
+
+	fn checkpoint(number: u32, set_id: u64) -> FinalitySyncCheckpoint {
+		FinalitySyncCheckpoint {
+			number,
+			set_id,
+			validator_set: vec![],
+		}
+	}
+
+	#[tokio::test]
+	async fn sync_from_trusted_authority_set() {
+		let mut client = MockClient::new();
+		client
+			.expect_get_genesis_hash()
+			.returning(|| Ok(H256::default()));
+		client
+			.expect_get_checkpoint()
+			.returning(|| Ok(Some(checkpoint(2, 1))));
+		// Genesis validator set is not fetched
+		client.expect_fetch_set_id_at().never();
+		client
+			.expect_get_block_hash()
+			.with(eq(3))
+			.returning(|_| Ok(H256::repeat_byte(3)));
+		client
+			.expect_get_block_hash()
+			.with(eq(4))
+			.returning(|_| Ok(H256::repeat_byte(4)));
+		client
+			.expect_get_header_by_hash()
+			.with(eq(H256::repeat_byte(4)))
+			.returning(|_| Ok(header(4, H256::repeat_byte(3))));
+		client
+			.expect_store_block_header()
+			.with(eq(4), always())
+			.times(1)
+			.returning(|_, _| Ok(()));
+
+		let state = Arc::new(Mutex::new(State::default()));
+		let from_header = header(4, H256::repeat_byte(3));
+		sync(client, state.clone(), from_header, Some(checkpoint(4, 3)))
+			.await
+			.unwrap();
+		assert!(state.lock().unwrap().finality_synced);
+	}
+
+	#[tokio::test]
+	async fn trusted_authority_set_ahead_of_finalized_header() {
+		let mut client = MockClient::new();
+		client
+			.expect_get_genesis_hash()
+			.returning(|| Ok(H256::default()));
+		client.expect_get_checkpoint().returning(|| Ok(None));
+		client.expect_get_block_hash().never();
+
+		let state = Arc::new(Mutex::new(State::default()));
+		let from_header = header(4, H256::default());
+		let result = sync(client, state.clone(), from_header, Some(checkpoint(5, 3))).await;
+		assert!(result.is_err());
+		assert!(!state.lock().unwrap().finality_synced);
+	}
+}
@@ -1,8 +1,7 @@
 use async_trait::async_trait;
 use color_eyre::Result;
 use opentelemetry_api::{
-	global,
-	metrics::{Counter, Meter},
+	metrics::{Counter, Meter, MeterProvider as _},
 	KeyValue,
 };
 use opentelemetry_otlp::{ExportConfig, Protocol, WithExportConfig};
@@ -15,6 +14,9 @@ const ATTRIBUTE_NUMBER: usize = 8;
 
 #[derive(Debug)]
 pub struct Metrics {
+	// Provider is owned by the metrics instead of being set globally,
+	// so multiple light clients in the same process export their metrics independently
+	_provider: opentelemetry_sdk::metrics::MeterProvider,
 	meter: Meter,
 	counters: HashMap<String, Counter<u64>>,
 	attributes: MetricAttributes,
@@ -173,11 +175,11 @@ pub fn initialize(endpoint: String, attributes: MetricAttributes) -> Result<Metr
 		.with_timeout(Duration::from_secs(15)) // Configures the time a OT waits for an export to complete before canceling it.
 		.build()?;
 
-	global::set_meter_provider(provider);
-	let meter = global::meter("avail_light_client");
+	let meter = provider.meter("avail_light_client");
 	// Initialize counters - they need to persist unlike Gauges that are recreated on every record
 	let initialized_counters = MetricCounter::init_counters(meter.clone());
 	Ok(Metrics {
+		_provider: provider,
 		meter,
 		attributes,
 		counters: initialized_counters,
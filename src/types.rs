@@ -1,5 +1,6 @@
 //! Shared light client structs and enums.
 
+use crate::data::FinalitySyncCheckpoint;
 use crate::network::p2p::MemoryStoreConfig;
 use crate::network::rpc::{Event, Node as RpcNode};
 use crate::utils::{extract_app_lookup, extract_kate};
@@ -23,13 +24,16 @@ use sp_core::crypto::Ss58Codec;
 use sp_core::{blake2_256, bytes, ed25519};
 use std::fmt::{self, Display, Formatter};
 use std::fs;
+use std::net::SocketAddr;
 use std::num::{NonZeroU8, NonZeroUsize};
 use std::ops::Range;
+use std::path::Path;
 use std::str::FromStr;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use subxt::ext::sp_core::{sr25519::Pair, Pair as _};
 use tokio::sync::broadcast;
 use tokio_retry::strategy::{jitter, ExponentialBackoff, FibonacciBackoff};
+use tracing::warn;
 
 const CELL_SIZE: usize = 32;
 const PROOF_SIZE: usize = 48;
@@ -130,7 +134,7 @@ impl TryFrom<(DaHeader, Option<f64>)> for BlockVerified {
 	}
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(try_from = "String")]
 pub enum KademliaMode {
 	Client,
@@ -319,6 +323,38 @@ pub struct FibonacciConfig {
 	pub retries: usize,
 }
 
+/// GRANDPA authority set, trusted to be active at the given block
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TrustedAuthoritySet {
+	pub block_number: u32,
+	pub set_id: u64,
+	/// Hex encoded ed25519 public keys of the authorities
+	pub authorities: Vec<String>,
+}
+
+impl TryFrom<&TrustedAuthoritySet> for FinalitySyncCheckpoint {
+	type Error = Report;
+
+	fn try_from(value: &TrustedAuthoritySet) -> Result<Self, Self::Error> {
+		let validator_set = value
+			.authorities
+			.iter()
+			.map(|authority| {
+				let mut key = [0u8; 32];
+				hex::decode_to_slice(authority.trim_start_matches("0x"), &mut key)
+					.wrap_err_with(|| format!("Invalid authority key {authority}"))?;
+				Ok(ed25519::Public::from_raw(key))
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		Ok(FinalitySyncCheckpoint {
+			number: value.block_number,
+			set_id: value.set_id,
+			validator_set,
+		})
+	}
+}
+
 /// Representation of a configuration used by this project.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(default)]
@@ -335,6 +371,19 @@ pub struct RuntimeConfig {
 	/// P2P service port (default: 37000).
 	pub port: u16,
 	pub ws_transport_enable: bool,
+	/// Enables listening on QUIC transport, on the same (UDP) port as the P2P service. Not used with WebSocket transport (default: false).
+	pub quic_transport_enable: bool,
+	/// Address (ip:port) of the SOCKS5 proxy (e.g. Tor) used for all outbound P2P connections. Host names are not resolved.
+	/// Listening is disabled when proxy is used, and mDNS discovery is turned off. Not supported with WebSocket transport (default: None).
+	pub socks5_proxy: Option<SocketAddr>,
+	/// Allows adding private (RFC1918, link-local) and loopback addresses, advertised by peers through Identify, to the routing table.
+	/// Needed for local multi-node setups (e.g. Docker Compose). Configured bootstrap nodes and mDNS peers are always used (default: false).
+	pub allow_private_ips: bool,
+	/// Peer IDs of the only peers which are allowed to connect, and to be connected to. Bootstrap and relay nodes are always allowed.
+	/// If not set, connections are not restricted (default: None).
+	pub peer_allowlist: Option<Vec<PeerId>>,
+	/// Persists known peers to the database, and dials them on the next startup, in addition to the bootstrap nodes (default: false).
+	pub persist_peers: bool,
 	/// Configures AutoNAT behaviour to reject probes as a server for clients that are observed at a non-global ip address (default: false)
 	pub autonat_only_global_ips: bool,
 	/// AutoNat throttle period for re-using a peer as server for a dial-request. (default: 1 sec)
@@ -367,6 +416,8 @@ pub struct RuntimeConfig {
 	pub origin: String,
 	/// If set to true, logs are displayed in JSON format, which is used for structured logging. Otherwise, plain text format is used (default: false).
 	pub log_format_json: bool,
+	/// File path where subscription events are recorded as JSON lines, for debugging (default: None).
+	pub event_recording_path: Option<String>,
 	/// OpenTelemetry Collector endpoint (default: `http://otelcollector.avail.tools:4317`)
 	pub ot_collector_endpoint: String,
 	/// Disables fetching of cells from RPC, set to true if client expects cells to be available in DHT (default: false).
@@ -384,6 +435,18 @@ pub struct RuntimeConfig {
 	pub sync_start_block: Option<u32>,
 	/// Enable or disable synchronizing finality. If disabled, finality is assumed to be verified until the starting block at the point the LC is started and is only checked for new blocks. (default: true)
 	pub sync_finality_enable: bool,
+	/// Trusted GRANDPA authority set at the given block, from which finality is synced instead of genesis.
+	/// Sync fails if the block is ahead of the latest finalized block (default: None).
+	pub sync_finality_trusted_authority_set: Option<TrustedAuthoritySet>,
+	/// Number of blocks the verified finalized header is allowed to lag behind the best block of the node, before finality lag is reported.
+	/// If not set, finality lag is not tracked (default: None).
+	pub finality_lag_threshold: Option<u32>,
+	/// Maximum number of cached block hash and header lookups, per lookup type. Caching is disabled if set to 0 (default: 128).
+	pub query_cache_size: usize,
+	/// Number of the most recently read blocks for which storage values are cached. Caching is disabled if set to 0 (default: 16).
+	pub storage_cache_size: usize,
+	/// Number of the latest header intervals used to estimate the average block time (default: 10).
+	pub block_time_window: usize,
 	/// Maximum number of cells per request for proof queries (default: 30).
 	pub max_cells_per_rpc: Option<usize>,
 	/// Threshold for the number of cells fetched via DHT for the app client (default: 5000)
@@ -409,6 +472,18 @@ pub struct RuntimeConfig {
 	/// Sets the amount of time to keep connections alive when they're idle. (default: 30s).
 	/// NOTE: libp2p default value is 10s, but because of Avail block time of 20s the value has been increased
 	pub connection_idle_timeout: u64,
+	/// Sets the time in seconds within which outbound TCP connection has to be established and upgraded,
+	/// otherwise the dial is aborted (default: 20s).
+	pub dial_timeout: u64,
+	/// Target number of outbound connections. When below the target, peers from the routing table are dialed.
+	/// If not set, outbound connections are not actively maintained (default: None).
+	pub out_peers: Option<u32>,
+	/// Maximum number of accepted inbound connections, beyond which inbound connections are closed.
+	/// If not set, inbound connections are not limited (default: None).
+	pub in_peers: Option<u32>,
+	/// Interval in seconds (greater than zero) in which the number of connections is sampled into the connection history.
+	/// If not set, connections are not sampled (default: None).
+	pub connection_sampling_interval: Option<u32>,
 	pub max_negotiating_inbound_streams: usize,
 	pub task_command_buffer_size: usize,
 	pub per_connection_event_buffer_size: usize,
@@ -449,8 +524,111 @@ impl RuntimeConfig {
 	pub fn is_fat_client(&self) -> bool {
 		self.block_matrix_partition.is_some()
 	}
+
+	/// Checks configuration values and their combinations up front,
+	/// instead of failing (or misbehaving) at runtime.
+	pub fn validate(&self) -> Result<(), ConfigError> {
+		if self.bootstraps.is_empty() {
+			return Err(ConfigError::EmptyBootstraps);
+		}
+		if self.full_node_ws.is_empty() {
+			return Err(ConfigError::EmptyFullNodes);
+		}
+		if !(self.confidence > 0.0 && self.confidence < 100.0) {
+			return Err(ConfigError::InvalidConfidence(self.confidence));
+		}
+		let http_server_address = format!("{}:{}", self.http_server_host, self.http_server_port);
+		if SocketAddr::from_str(&http_server_address).is_err() {
+			return Err(ConfigError::InvalidHttpServerAddress(http_server_address));
+		}
+		if let Some(authority_set) = &self.sync_finality_trusted_authority_set {
+			if let Err(error) = FinalitySyncCheckpoint::try_from(authority_set) {
+				return Err(ConfigError::InvalidTrustedAuthoritySet(error.to_string()));
+			}
+		}
+		if self.socks5_proxy.is_some() && self.ws_transport_enable {
+			return Err(ConfigError::ProxyWithWebSocketTransport);
+		}
+		let zero_values = [
+			("replication_factor", self.replication_factor == 0),
+			("query_parallelism", self.query_parallelism == 0),
+			(
+				"task_command_buffer_size",
+				self.task_command_buffer_size == 0,
+			),
+			("dial_concurrency_factor", self.dial_concurrency_factor == 0),
+			("block_time_window", self.block_time_window == 0),
+			("dial_timeout", self.dial_timeout == 0),
+			(
+				"connection_sampling_interval",
+				self.connection_sampling_interval == Some(0),
+			),
+		];
+		if let Some((name, _)) = zero_values.into_iter().find(|(_, is_zero)| *is_zero) {
+			return Err(ConfigError::ZeroValue(name));
+		}
+		// Short record TTL is recommended for fat clients, even with the default intervals
+		if self.kad_record_ttl <= self.publication_interval.into()
+			|| self.publication_interval <= self.replication_interval
+		{
+			warn!(
+				record_ttl = self.kad_record_ttl,
+				publication_interval = self.publication_interval,
+				replication_interval = self.replication_interval,
+				"Kademlia record TTL should be greater than publication interval, which should be greater than replication interval"
+			);
+		}
+		if !is_writable_path(Path::new(&self.avail_path)) {
+			return Err(ConfigError::UnwritableDatabasePath(self.avail_path.clone()));
+		}
+		Ok(())
+	}
+}
+
+/// Checks if the path (or its closest existing ancestor) is writable
+fn is_writable_path(path: &Path) -> bool {
+	path.ancestors()
+		.map(|path| {
+			if path.as_os_str().is_empty() {
+				Path::new(".")
+			} else {
+				path
+			}
+		})
+		.find_map(|path| fs::metadata(path).ok())
+		.map_or(false, |metadata| !metadata.permissions().readonly())
+}
+
+/// Invalid configuration value, or combination of values
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+	EmptyBootstraps,
+	EmptyFullNodes,
+	InvalidConfidence(f64),
+	InvalidHttpServerAddress(String),
+	ProxyWithWebSocketTransport,
+	InvalidTrustedAuthoritySet(String),
+	ZeroValue(&'static str),
+	UnwritableDatabasePath(String),
 }
 
+impl Display for ConfigError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			ConfigError::EmptyBootstraps => write!(f, "Bootstrap node list must not be empty. Either use a '--network' flag or add a list of bootstrap nodes in the configuration file"),
+			ConfigError::EmptyFullNodes => write!(f, "Full node WebSocket endpoint list must not be empty"),
+			ConfigError::InvalidConfidence(confidence) => write!(f, "Confidence must be between 0 and 100 (exclusive), but it is set to {confidence}"),
+			ConfigError::InvalidHttpServerAddress(address) => write!(f, "HTTP server address {address} is not valid"),
+			ConfigError::ProxyWithWebSocketTransport => write!(f, "SOCKS5 proxy cannot be used with WebSocket transport"),
+			ConfigError::InvalidTrustedAuthoritySet(error) => write!(f, "Trusted authority set is not valid: {error}"),
+			ConfigError::ZeroValue(name) => write!(f, "Configuration parameter {name} must be greater than zero"),
+			ConfigError::UnwritableDatabasePath(path) => write!(f, "Database path {path} is not writable"),
+		}
+	}
+}
+
+impl std::error::Error for ConfigError {}
+
 pub struct Delay(pub Option<Duration>);
 
 /// Light client configuration (see [RuntimeConfig] for details)
@@ -515,12 +693,19 @@ impl From<&RuntimeConfig> for FatClientConfig {
 pub struct LibP2PConfig {
 	pub secret_key: Option<SecretKey>,
 	pub port: u16,
+	pub socks5_proxy: Option<SocketAddr>,
+	pub allow_private_ips: bool,
+	pub peer_allowlist: Option<Vec<PeerId>>,
 	pub identify: IdentifyConfig,
 	pub autonat: AutoNATConfig,
 	pub kademlia: KademliaConfig,
 	pub relays: Vec<(PeerId, Multiaddr)>,
 	pub bootstrap_interval: Duration,
 	pub connection_idle_timeout: Duration,
+	pub dial_timeout: Duration,
+	pub out_peers: Option<u32>,
+	pub in_peers: Option<u32>,
+	pub connection_sampling_interval: Option<Duration>,
 	pub max_negotiating_inbound_streams: usize,
 	pub task_command_buffer_size: NonZeroUsize,
 	pub per_connection_event_buffer_size: usize,
@@ -568,12 +753,25 @@ impl From<&RuntimeConfig> for LibP2PConfig {
 		Self {
 			secret_key: val.secret_key.clone(),
 			port: val.port,
+			socks5_proxy: val.socks5_proxy,
+			allow_private_ips: val.allow_private_ips,
+			peer_allowlist: val.peer_allowlist.as_ref().map(|allowlist| {
+				let nodes = val.bootstraps.iter().chain(&val.relays);
+				let node_peers = nodes.map(|node| <(PeerId, Multiaddr)>::from(node).0);
+				allowlist.iter().copied().chain(node_peers).collect()
+			}),
 			identify: val.into(),
 			autonat: val.into(),
 			kademlia: val.into(),
 			relays: val.relays.iter().map(Into::into).collect(),
 			bootstrap_interval: Duration::from_secs(val.bootstrap_period),
 			connection_idle_timeout: Duration::from_secs(val.connection_idle_timeout),
+			dial_timeout: Duration::from_secs(val.dial_timeout),
+			out_peers: val.out_peers,
+			in_peers: val.in_peers,
+			connection_sampling_interval: val
+				.connection_sampling_interval
+				.map(|interval| Duration::from_secs(interval.into())),
 			max_negotiating_inbound_streams: val.max_negotiating_inbound_streams,
 			task_command_buffer_size: std::num::NonZeroUsize::new(val.task_command_buffer_size)
 				.expect("Invalid task command buffer size"),
@@ -751,6 +949,38 @@ impl From<&RuntimeConfig> for AppClientConfig {
 		}
 	}
 }
+/// RPC subscriptions configuration (see [RuntimeConfig] for details)
+#[derive(Clone, Default)]
+pub struct SubscriptionsConfig {
+	pub finality_lag_threshold: Option<u32>,
+	pub block_time_window: usize,
+}
+
+impl From<&RuntimeConfig> for SubscriptionsConfig {
+	fn from(val: &RuntimeConfig) -> Self {
+		SubscriptionsConfig {
+			finality_lag_threshold: val.finality_lag_threshold,
+			block_time_window: val.block_time_window,
+		}
+	}
+}
+
+/// RPC query cache configuration (see [RuntimeConfig] for details)
+#[derive(Clone, Default)]
+pub struct QueryCacheConfig {
+	pub query_cache_size: usize,
+	pub storage_cache_size: usize,
+}
+
+impl From<&RuntimeConfig> for QueryCacheConfig {
+	fn from(val: &RuntimeConfig) -> Self {
+		QueryCacheConfig {
+			query_cache_size: val.query_cache_size,
+			storage_cache_size: val.storage_cache_size,
+		}
+	}
+}
+
 impl Default for RuntimeConfig {
 	fn default() -> Self {
 		RuntimeConfig {
@@ -758,6 +988,11 @@ impl Default for RuntimeConfig {
 			http_server_port: 7000,
 			port: 37000,
 			ws_transport_enable: false,
+			quic_transport_enable: false,
+			socks5_proxy: None,
+			allow_private_ips: false,
+			peer_allowlist: None,
+			persist_peers: false,
 			secret_key: None,
 			autonat_only_global_ips: false,
 			autonat_refresh_interval: 360,
@@ -774,6 +1009,7 @@ impl Default for RuntimeConfig {
 			avail_path: "avail_path".to_owned(),
 			log_level: "INFO".to_owned(),
 			log_format_json: false,
+			event_recording_path: None,
 			ot_collector_endpoint: "http://127.0.0.1:4317".to_string(),
 			disable_rpc: false,
 			dht_parallelization_limit: 20,
@@ -782,6 +1018,11 @@ impl Default for RuntimeConfig {
 			block_matrix_partition: None,
 			sync_start_block: None,
 			sync_finality_enable: false,
+			sync_finality_trusted_authority_set: None,
+			finality_lag_threshold: None,
+			query_cache_size: 128,
+			storage_cache_size: 16,
+			block_time_window: 10,
 			max_cells_per_rpc: Some(30),
 			kad_record_ttl: 24 * 60 * 60,
 			threshold: 5000,
@@ -789,6 +1030,10 @@ impl Default for RuntimeConfig {
 			publication_interval: 12 * 60 * 60,
 			replication_interval: 3 * 60 * 60,
 			connection_idle_timeout: 30,
+			dial_timeout: 20,
+			out_peers: None,
+			in_peers: None,
+			connection_sampling_interval: None,
 			max_negotiating_inbound_streams: 128,
 			task_command_buffer_size: 32,
 			per_connection_event_buffer_size: 7,
@@ -1019,6 +1264,65 @@ pub struct State {
 	pub sync_data_verified: Option<BlockRange>,
 	pub finality_synced: bool,
 	pub connected_node: RpcNode,
+	/// Average interval between received headers, over the configured window
+	pub average_block_time: Option<Duration>,
+	/// Time when the latest header was received
+	pub latest_received_at: Option<SystemTime>,
+	/// Latest header with verified finality
+	pub finalized_header: Option<DaHeader>,
+}
+
+/// Current sync phase of the light client
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SyncState {
+	/// No headers are received yet
+	Idle,
+	/// Finality of the blocks before the startup is being verified
+	FinalitySyncing,
+	/// Historical blocks are being processed, with the latest processed historical block
+	Backfilling { latest: Option<u32> },
+	/// Latest finalized blocks are being processed
+	Following,
+}
+
+impl SyncState {
+	/// Returns true if both sync states are in the same phase, regardless of the progress
+	pub fn is_same_phase(&self, other: &SyncState) -> bool {
+		std::mem::discriminant(self) == std::mem::discriminant(other)
+	}
+}
+
+impl State {
+	pub fn sync_state(&self) -> SyncState {
+		if self.latest == 0 {
+			SyncState::Idle
+		} else if !self.finality_synced {
+			SyncState::FinalitySyncing
+		} else if self.synced == Some(false) {
+			SyncState::Backfilling {
+				latest: self.sync_latest,
+			}
+		} else {
+			SyncState::Following
+		}
+	}
+
+	/// Estimates the number of the latest block at the given time, based on the time the latest header
+	/// was received and the average block time. Returns `None` until the average block time is known.
+	pub fn estimate_block_at_time(&self, when: SystemTime) -> Option<u32> {
+		let average_block_time = self.average_block_time.filter(|time| !time.is_zero())?;
+		let received_at = self.latest_received_at.filter(|_| self.latest != 0)?;
+		let blocks = |elapsed: Duration| elapsed.as_secs_f64() / average_block_time.as_secs_f64();
+
+		match when.duration_since(received_at) {
+			Ok(elapsed) => Some(self.latest.saturating_add(blocks(elapsed) as u32)),
+			// Block before the latest one was the latest until the latest header was received
+			Err(error) => Some(
+				self.latest
+					.saturating_sub(blocks(error.duration()).ceil() as u32),
+			),
+		}
+	}
 }
 
 pub trait OptionBlockRange {
@@ -1107,3 +1411,155 @@ impl TimeToLive {
 		Instant::now().checked_add(self.0)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		ConfigError, MultiaddrConfig, RuntimeConfig, State, SyncState, TrustedAuthoritySet,
+	};
+	use libp2p::{Multiaddr, PeerId};
+	use std::time::{Duration, SystemTime};
+
+	fn runtime_config() -> RuntimeConfig {
+		RuntimeConfig {
+			bootstraps: vec![MultiaddrConfig::PeerIdAndMultiaddr((
+				PeerId::random(),
+				Multiaddr::empty(),
+			))],
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn validate_ok() {
+		assert_eq!(runtime_config().validate(), Ok(()));
+	}
+
+	#[test]
+	fn validate_errors() {
+		let cfg = RuntimeConfig::default();
+		assert_eq!(cfg.validate(), Err(ConfigError::EmptyBootstraps));
+
+		let cfg = RuntimeConfig {
+			confidence: 100.0,
+			..runtime_config()
+		};
+		assert_eq!(cfg.validate(), Err(ConfigError::InvalidConfidence(100.0)));
+
+		let cfg = RuntimeConfig {
+			http_server_host: "localhost:".to_string(),
+			..runtime_config()
+		};
+		assert!(matches!(
+			cfg.validate(),
+			Err(ConfigError::InvalidHttpServerAddress(_))
+		));
+
+		let cfg = RuntimeConfig {
+			sync_finality_trusted_authority_set: Some(TrustedAuthoritySet {
+				block_number: 10,
+				set_id: 1,
+				authorities: vec!["0x1234".to_string()],
+			}),
+			..runtime_config()
+		};
+		assert!(matches!(
+			cfg.validate(),
+			Err(ConfigError::InvalidTrustedAuthoritySet(_))
+		));
+
+		let cfg = RuntimeConfig {
+			socks5_proxy: Some("127.0.0.1:9050".parse().unwrap()),
+			ws_transport_enable: true,
+			..runtime_config()
+		};
+		assert_eq!(
+			cfg.validate(),
+			Err(ConfigError::ProxyWithWebSocketTransport)
+		);
+
+		let cfg = RuntimeConfig {
+			query_parallelism: 0,
+			..runtime_config()
+		};
+		assert_eq!(
+			cfg.validate(),
+			Err(ConfigError::ZeroValue("query_parallelism"))
+		);
+
+		let cfg = RuntimeConfig {
+			connection_sampling_interval: Some(0),
+			..runtime_config()
+		};
+		assert_eq!(
+			cfg.validate(),
+			Err(ConfigError::ZeroValue("connection_sampling_interval"))
+		);
+
+		let cfg = RuntimeConfig {
+			dial_timeout: 0,
+			..runtime_config()
+		};
+		assert_eq!(cfg.validate(), Err(ConfigError::ZeroValue("dial_timeout")));
+
+		// Recommended fat client record TTL is accepted with the default intervals
+		let cfg = RuntimeConfig {
+			kad_record_ttl: 60 * 60,
+			..runtime_config()
+		};
+		assert_eq!(cfg.validate(), Ok(()));
+	}
+
+	#[test]
+	fn sync_state() {
+		let mut state = State::default();
+		assert_eq!(state.sync_state(), SyncState::Idle);
+
+		state.latest = 10;
+		assert_eq!(state.sync_state(), SyncState::FinalitySyncing);
+
+		state.finality_synced = true;
+		state.synced = Some(false);
+		state.sync_latest = Some(5);
+		assert_eq!(
+			state.sync_state(),
+			SyncState::Backfilling { latest: Some(5) }
+		);
+		assert!(state
+			.sync_state()
+			.is_same_phase(&SyncState::Backfilling { latest: None }));
+
+		state.synced = Some(true);
+		assert_eq!(state.sync_state(), SyncState::Following);
+	}
+
+	#[test]
+	fn estimate_block_at_time() {
+		let received_at = SystemTime::now();
+		let mut state = State {
+			latest: 100,
+			latest_received_at: Some(received_at),
+			..Default::default()
+		};
+		assert_eq!(state.estimate_block_at_time(received_at), None);
+
+		state.average_block_time = Some(Duration::from_secs(20));
+		assert_eq!(state.estimate_block_at_time(received_at), Some(100));
+		let later = received_at + Duration::from_secs(50);
+		assert_eq!(state.estimate_block_at_time(later), Some(102));
+		let earlier = received_at - Duration::from_secs(10);
+		assert_eq!(state.estimate_block_at_time(earlier), Some(99));
+		let earlier = received_at - Duration::from_secs(40);
+		assert_eq!(state.estimate_block_at_time(earlier), Some(98));
+	}
+
+	#[test]
+	fn socks5_proxy_address() {
+		let cfg: RuntimeConfig =
+			serde_json::from_str(r#"{"socks5_proxy":"127.0.0.1:9050"}"#).unwrap();
+		assert_eq!(cfg.socks5_proxy, Some("127.0.0.1:9050".parse().unwrap()));
+
+		let cfg = serde_json::from_str::<RuntimeConfig>(r#"{"socks5_proxy":"localhost:9050"}"#);
+		assert!(cfg.is_err());
+	}
+}
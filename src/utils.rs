@@ -89,6 +89,16 @@ pub fn filter_auth_set_changes(header: &DaHeader) -> Vec<Vec<(AuthorityId, u64)>
 	new_auths
 }
 
+/// Checks if the runtime is upgraded in the block, which is signalled in the header digest
+pub fn is_runtime_upgraded(header: &DaHeader) -> bool {
+	header.digest.logs.iter().any(|log| {
+		matches!(
+			log,
+			avail_subxt::config::substrate::DigestItem::RuntimeEnvironmentUpdated
+		)
+	})
+}
+
 // TODO: Remove unused functions if not needed after next iteration
 
 #[allow(dead_code)]